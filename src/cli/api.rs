@@ -0,0 +1,203 @@
+use anyhow::{bail, Result};
+use clap::Subcommand;
+use rusqlite::Connection;
+
+use crate::db;
+use crate::models::ClientType;
+
+#[derive(Subcommand)]
+pub enum ApiAction {
+    /// Register an API that clients can request access tokens for
+    Add {
+        /// Realm name
+        #[arg(long)]
+        realm: String,
+        /// Absolute URI identifying the API; clients pass it as `resource`
+        #[arg(long)]
+        identifier: String,
+        /// Display name
+        #[arg(long)]
+        name: Option<String>,
+        /// Scope the API understands (can be specified multiple times)
+        #[arg(long)]
+        scope: Vec<String>,
+    },
+    /// List APIs in a realm
+    List {
+        /// Realm name
+        #[arg(long)]
+        realm: String,
+    },
+    /// Let a client exchange users' tokens for tokens to this API (RFC 8693)
+    AllowExchange {
+        /// Realm name
+        #[arg(long)]
+        realm: String,
+        /// API identifier
+        #[arg(long)]
+        identifier: String,
+        /// Client that may exchange tokens
+        #[arg(long)]
+        client_id: String,
+    },
+    /// Stop a client exchanging tokens for tokens to this API
+    DisallowExchange {
+        /// Realm name
+        #[arg(long)]
+        realm: String,
+        /// API identifier
+        #[arg(long)]
+        identifier: String,
+        /// Client ID
+        #[arg(long)]
+        client_id: String,
+    },
+    /// Remove an API from a realm
+    Remove {
+        /// Realm name
+        #[arg(long)]
+        realm: String,
+        /// API identifier
+        #[arg(long)]
+        identifier: String,
+    },
+}
+
+pub fn handle(action: ApiAction, conn: &Connection) -> Result<()> {
+    match action {
+        ApiAction::Add {
+            realm,
+            identifier,
+            name,
+            scope,
+        } => {
+            let realm_obj = db::realm::get_realm_by_name(conn, &realm)?;
+            let realm_obj = match realm_obj {
+                Some(r) => r,
+                None => bail!("Realm '{realm}' not found"),
+            };
+
+            // RFC 8707 §2: an absolute URI without a fragment
+            match url::Url::parse(&identifier) {
+                Ok(url) if url.fragment().is_none() => {}
+                _ => bail!("Identifier must be an absolute URI without a fragment"),
+            }
+            let scopes: Vec<String> = scope
+                .iter()
+                .flat_map(|s| s.split_whitespace())
+                .map(str::to_string)
+                .collect();
+
+            let api = db::resource_server::create_resource_server(
+                conn,
+                &realm_obj.id,
+                &identifier,
+                name.as_deref(),
+                &scopes,
+            )?;
+            println!(
+                "Registered API '{}' in realm '{}' (id: {})",
+                api.identifier, realm, api.id
+            );
+        }
+        ApiAction::List { realm } => {
+            let realm_obj = db::realm::get_realm_by_name(conn, &realm)?;
+            let realm_obj = match realm_obj {
+                Some(r) => r,
+                None => bail!("Realm '{realm}' not found"),
+            };
+
+            let apis = db::resource_server::list_resource_servers(conn, &realm_obj.id)?;
+            if apis.is_empty() {
+                println!("No APIs in realm '{realm}'.");
+            } else {
+                for api in apis {
+                    println!(
+                        "{:<40} {}",
+                        api.identifier,
+                        api.name.as_deref().unwrap_or("")
+                    );
+                    if !api.scopes.is_empty() {
+                        println!("  scopes: {}", api.scopes.join(" "));
+                    }
+                    for client_id in db::exchange_policy::list_exchange_clients(
+                        conn,
+                        &realm_obj.id,
+                        &api.identifier,
+                    )? {
+                        println!("  token exchange: {client_id}");
+                    }
+                }
+            }
+        }
+        ApiAction::AllowExchange {
+            realm,
+            identifier,
+            client_id,
+        } => {
+            let realm_obj = db::realm::get_realm_by_name(conn, &realm)?;
+            let realm_obj = match realm_obj {
+                Some(r) => r,
+                None => bail!("Realm '{realm}' not found"),
+            };
+            if db::resource_server::get_resource_server(conn, &realm_obj.id, &identifier)?.is_none()
+            {
+                bail!("API '{identifier}' not found in realm '{realm}'");
+            }
+            let Some(client) =
+                db::client::get_client_by_client_id(conn, &realm_obj.id, &client_id)?
+            else {
+                bail!("Client '{client_id}' not found in realm '{realm}'");
+            };
+            if client.client_type != ClientType::Confidential {
+                bail!("Only confidential clients can exchange tokens");
+            }
+
+            db::exchange_policy::allow_token_exchange(
+                conn,
+                &realm_obj.id,
+                &client_id,
+                &identifier,
+            )?;
+            println!("Client '{client_id}' may now exchange tokens for '{identifier}'");
+        }
+        ApiAction::DisallowExchange {
+            realm,
+            identifier,
+            client_id,
+        } => {
+            let realm_obj = db::realm::get_realm_by_name(conn, &realm)?;
+            let realm_obj = match realm_obj {
+                Some(r) => r,
+                None => bail!("Realm '{realm}' not found"),
+            };
+
+            if db::exchange_policy::disallow_token_exchange(
+                conn,
+                &realm_obj.id,
+                &client_id,
+                &identifier,
+            )? {
+                println!("Client '{client_id}' may no longer exchange tokens for '{identifier}'");
+            } else {
+                println!(
+                    "Client '{client_id}' was not allowed to exchange tokens for '{identifier}'"
+                );
+            }
+        }
+        ApiAction::Remove { realm, identifier } => {
+            let realm_obj = db::realm::get_realm_by_name(conn, &realm)?;
+            let realm_obj = match realm_obj {
+                Some(r) => r,
+                None => bail!("Realm '{realm}' not found"),
+            };
+
+            if db::resource_server::delete_resource_server(conn, &realm_obj.id, &identifier)? {
+                println!("Removed API '{identifier}' from realm '{realm}'");
+            } else {
+                println!("API '{identifier}' not found in realm '{realm}'");
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,8 +1,15 @@
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
+use chrono::{Duration, Utc};
 use clap::Subcommand;
 use rusqlite::Connection;
+use sha2::{Digest, Sha256};
 
+use crate::crypto::keys::parse_jwks;
+use crate::crypto::password::{generate_client_secret, hash_password};
+use crate::crypto::token::hex_encode;
 use crate::db;
+use crate::models::ClientType;
+use crate::server::subject::redirect_hosts;
 
 #[derive(Subcommand)]
 pub enum ClientAction {
@@ -14,9 +21,45 @@ pub enum ClientAction {
         /// Client ID (application identifier)
         #[arg(long)]
         client_id: String,
+        /// Name shown to users on the consent screen
+        #[arg(long)]
+        name: Option<String>,
+        /// Treat as a third-party client: users must consent before it gets tokens
+        #[arg(long)]
+        third_party: bool,
+        /// Require authorization requests to be pushed to the PAR endpoint first
+        #[arg(long)]
+        require_par: bool,
+        /// Issue pairwise subject identifiers so the client can't correlate users
+        #[arg(long)]
+        pairwise: bool,
+        /// Sector identifier for pairwise subjects (defaults to the redirect URI host)
+        #[arg(long)]
+        sector_identifier: Option<String>,
         /// Redirect URI (can be specified multiple times)
         #[arg(long)]
         redirect_uri: Vec<String>,
+        /// Post-logout redirect URI (can be specified multiple times)
+        #[arg(long)]
+        post_logout_redirect_uri: Vec<String>,
+        /// URL a signed request object may be fetched from (can be specified multiple times)
+        #[arg(long)]
+        request_uri: Vec<String>,
+        /// URI that receives OIDC back-channel logout tokens when a session ends
+        #[arg(long)]
+        backchannel_logout_uri: Option<String>,
+        /// URI loaded in an iframe on the logout page (OIDC front-channel logout)
+        #[arg(long)]
+        frontchannel_logout_uri: Option<String>,
+        /// Register a confidential client and generate a client secret
+        #[arg(long)]
+        confidential: bool,
+        /// Public JWK or JWKS (JSON) for private_key_jwt authentication
+        #[arg(long, conflicts_with = "jwks_file")]
+        jwk: Option<String>,
+        /// Path to a JWKS file for private_key_jwt authentication, re-read on each use
+        #[arg(long)]
+        jwks_file: Option<String>,
     },
     /// List clients in a realm
     List {
@@ -24,6 +67,45 @@ pub enum ClientAction {
         #[arg(long)]
         realm: String,
     },
+    /// Update a registered client
+    Update {
+        /// Realm name
+        #[arg(long)]
+        realm: String,
+        /// Client ID
+        #[arg(long)]
+        client_id: String,
+        /// Scopes the client may request (space- or comma-separated; replaces the list)
+        #[arg(long, value_delimiter = ',', num_args = 1..)]
+        scopes: Option<Vec<String>>,
+        /// Name shown to users on the consent screen
+        #[arg(long)]
+        name: Option<String>,
+        /// Whether the client is first-party (skips the consent screen)
+        #[arg(long)]
+        first_party: Option<bool>,
+        /// Whether authorization requests must be pushed to the PAR endpoint first
+        #[arg(long)]
+        require_par: Option<bool>,
+        /// Whether to issue pairwise subject identifiers
+        #[arg(long)]
+        pairwise: Option<bool>,
+        /// Sector identifier for pairwise subjects
+        #[arg(long)]
+        sector_identifier: Option<String>,
+    },
+    /// Mint an initial access token for dynamic client registration
+    RegistrationToken {
+        /// Realm name
+        #[arg(long)]
+        realm: String,
+        /// Lifetime of the token in seconds
+        #[arg(long, default_value_t = 86400)]
+        expires_in: u64,
+        /// Number of clients the token may register
+        #[arg(long, default_value_t = 1)]
+        uses: u32,
+    },
     /// Remove a client from a realm
     Remove {
         /// Realm name
@@ -40,7 +122,19 @@ pub fn handle(action: ClientAction, conn: &Connection) -> Result<()> {
         ClientAction::Add {
             realm,
             client_id,
+            name,
+            third_party,
+            require_par,
+            pairwise,
+            sector_identifier,
             redirect_uri,
+            post_logout_redirect_uri,
+            request_uri,
+            backchannel_logout_uri,
+            frontchannel_logout_uri,
+            confidential,
+            jwk,
+            jwks_file,
         } => {
             let realm_obj = db::realm::get_realm_by_name(conn, &realm)?;
             let realm_obj = match realm_obj {
@@ -48,14 +142,83 @@ pub fn handle(action: ClientAction, conn: &Connection) -> Result<()> {
                 None => bail!("Realm '{realm}' not found"),
             };
 
-            let client = db::client::create_client(conn, &realm_obj.id, &client_id, &redirect_uri)?;
+            if let Some(jwk) = &jwk {
+                parse_jwks(jwk).context("parsing --jwk")?;
+            }
+            if let Some(path) = &jwks_file {
+                let contents =
+                    std::fs::read_to_string(path).with_context(|| format!("reading {path}"))?;
+                parse_jwks(&contents).with_context(|| format!("parsing {path}"))?;
+            }
+
+            // Clients with registered keys authenticate with private_key_jwt
+            // and don't need a shared secret.
+            let has_keys = jwk.is_some() || jwks_file.is_some();
+            let (client_type, secret) = if has_keys {
+                (ClientType::Confidential, None)
+            } else if confidential {
+                (ClientType::Confidential, Some(generate_client_secret()))
+            } else {
+                (ClientType::Public, None)
+            };
+            let secret_hash = secret.as_deref().map(hash_password).transpose()?;
+
+            if pairwise && sector_identifier.is_none() && redirect_hosts(&redirect_uri).len() > 1 {
+                bail!("Redirect URIs span several hosts; pass --sector-identifier");
+            }
+
+            let client = db::client::create_client(
+                conn,
+                &db::client::NewClient {
+                    realm_id: &realm_obj.id,
+                    client_id: &client_id,
+                    client_type,
+                    client_secret_hash: secret_hash.as_deref(),
+                    jwks: jwk.as_deref(),
+                    jwks_path: jwks_file.as_deref(),
+                    redirect_uris: &redirect_uri,
+                    post_logout_redirect_uris: &post_logout_redirect_uri,
+                    request_uris: &request_uri,
+                    backchannel_logout_uri: backchannel_logout_uri.as_deref(),
+                    frontchannel_logout_uri: frontchannel_logout_uri.as_deref(),
+                    display_name: name.as_deref(),
+                    first_party: !third_party,
+                    require_par,
+                    registration_token_hash: None,
+                    pairwise,
+                    sector_identifier: sector_identifier.as_deref(),
+                    grant_types: None,
+                },
+            )?;
             println!(
-                "Created client '{}' in realm '{}' (id: {})",
-                client.client_id, realm, client.id
+                "Created {} client '{}' in realm '{}' (id: {})",
+                client.client_type.as_str(),
+                client.client_id,
+                realm,
+                client.id
             );
             for uri in &client.redirect_uris {
                 println!("  redirect_uri: {uri}");
             }
+            for uri in &client.post_logout_redirect_uris {
+                println!("  post_logout_redirect_uri: {uri}");
+            }
+            for uri in &client.request_uris {
+                println!("  request_uri: {uri}");
+            }
+            if let Some(uri) = &client.backchannel_logout_uri {
+                println!("  backchannel_logout_uri: {uri}");
+            }
+            if let Some(uri) = &client.frontchannel_logout_uri {
+                println!("  frontchannel_logout_uri: {uri}");
+            }
+            if let Some(path) = &client.jwks_path {
+                println!("  jwks_file: {path}");
+            }
+            if let Some(secret) = secret {
+                println!("  client_secret: {secret}");
+                println!("Store the client secret now; it cannot be shown again.");
+            }
         }
         ClientAction::List { realm } => {
             let realm_obj = db::realm::get_realm_by_name(conn, &realm)?;
@@ -69,13 +232,143 @@ pub fn handle(action: ClientAction, conn: &Connection) -> Result<()> {
                 println!("No clients in realm '{realm}'.");
             } else {
                 for c in clients {
-                    println!("{:<20} {}", c.client_id, c.id);
+                    println!(
+                        "{:<20} {:<12} {}",
+                        c.client_id,
+                        c.client_type.as_str(),
+                        c.id
+                    );
+                    if let Some(name) = &c.display_name {
+                        println!("  name: {name}");
+                    }
+                    if !c.first_party {
+                        println!("  third-party (consent required)");
+                    }
+                    if c.require_par {
+                        println!("  pushed authorization requests required");
+                    }
+                    if c.pairwise {
+                        match &c.sector_identifier {
+                            Some(sector) => println!("  pairwise subjects (sector: {sector})"),
+                            None => println!("  pairwise subjects"),
+                        }
+                    }
                     for uri in &c.redirect_uris {
                         println!("  redirect_uri: {uri}");
                     }
+                    for uri in &c.post_logout_redirect_uris {
+                        println!("  post_logout_redirect_uri: {uri}");
+                    }
+                    for uri in &c.request_uris {
+                        println!("  request_uri: {uri}");
+                    }
+                    if let Some(uri) = &c.backchannel_logout_uri {
+                        println!("  backchannel_logout_uri: {uri}");
+                    }
+                    if let Some(uri) = &c.frontchannel_logout_uri {
+                        println!("  frontchannel_logout_uri: {uri}");
+                    }
+                    println!("  allowed_scopes: {}", c.allowed_scopes.join(" "));
                 }
             }
         }
+        ClientAction::Update {
+            realm,
+            client_id,
+            scopes,
+            name,
+            first_party,
+            require_par,
+            pairwise,
+            sector_identifier,
+        } => {
+            let realm_obj = db::realm::get_realm_by_name(conn, &realm)?;
+            let realm_obj = match realm_obj {
+                Some(r) => r,
+                None => bail!("Realm '{realm}' not found"),
+            };
+
+            let scopes: Option<Vec<String>> = scopes.map(|scopes| {
+                scopes
+                    .iter()
+                    .flat_map(|s| s.split_whitespace())
+                    .map(str::to_string)
+                    .collect()
+            });
+            let update = db::client::ClientUpdate {
+                allowed_scopes: scopes.as_deref(),
+                display_name: name.as_deref(),
+                first_party,
+                require_par,
+                pairwise,
+                sector_identifier: sector_identifier.as_deref(),
+                ..Default::default()
+            };
+            if update.allowed_scopes.is_none()
+                && update.display_name.is_none()
+                && update.first_party.is_none()
+                && update.require_par.is_none()
+                && update.pairwise.is_none()
+                && update.sector_identifier.is_none()
+            {
+                bail!(
+                    "Nothing to update; pass --scopes, --name, --first-party, --require-par, \
+                     --pairwise or --sector-identifier"
+                );
+            }
+
+            if let Some(client) =
+                db::client::get_client_by_client_id(conn, &realm_obj.id, &client_id)?
+            {
+                let pairwise = pairwise.unwrap_or(client.pairwise);
+                let has_sector = sector_identifier.is_some() || client.sector_identifier.is_some();
+                if pairwise && !has_sector && redirect_hosts(&client.redirect_uris).len() > 1 {
+                    bail!("Redirect URIs span several hosts; pass --sector-identifier");
+                }
+            }
+
+            if !db::client::update_client(conn, &realm_obj.id, &client_id, &update)? {
+                bail!("Client '{client_id}' not found in realm '{realm}'");
+            }
+            println!("Updated client '{client_id}' in realm '{realm}'");
+        }
+        ClientAction::RegistrationToken {
+            realm,
+            expires_in,
+            uses,
+        } => {
+            let realm_obj = db::realm::get_realm_by_name(conn, &realm)?;
+            let realm_obj = match realm_obj {
+                Some(r) => r,
+                None => bail!("Realm '{realm}' not found"),
+            };
+            if uses == 0 {
+                bail!("--uses must be at least 1");
+            }
+
+            let expires_at = i64::try_from(expires_in)
+                .ok()
+                .and_then(Duration::try_seconds)
+                .and_then(|lifetime| Utc::now().checked_add_signed(lifetime))
+                .context("--expires-in is too large")?;
+
+            let token = generate_client_secret();
+            let token_hash = hex_encode(&Sha256::digest(token.as_bytes()));
+            db::initial_access_token::create_initial_access_token(
+                conn,
+                &realm_obj.id,
+                &token_hash,
+                uses,
+                expires_at,
+            )?;
+            println!("{token}");
+            if !realm_obj.registration_enabled {
+                eprintln!(
+                    "Note: registration is disabled in realm '{realm}'; enable it with \
+                     `anz realm update {realm} --registration true`"
+                );
+            }
+        }
         ClientAction::Remove { realm, client_id } => {
             let realm_obj = db::realm::get_realm_by_name(conn, &realm)?;
             let realm_obj = match realm_obj {
@@ -1,3 +1,4 @@
+pub mod api;
 pub mod client;
 pub mod realm;
 pub mod serve;
@@ -34,6 +35,11 @@ pub enum Commands {
         #[command(subcommand)]
         action: client::ClientAction,
     },
+    /// Manage the APIs access tokens can be issued for
+    Api {
+        #[command(subcommand)]
+        action: api::ApiAction,
+    },
     /// Start the HTTP server
     Serve,
 }
@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use clap::Subcommand;
 use rusqlite::Connection;
 
@@ -13,6 +13,15 @@ pub enum RealmAction {
     },
     /// List all realms
     List,
+    /// Update realm settings
+    Update {
+        /// Realm name
+        name: String,
+        /// Whether clients may register themselves (RFC 7591) with an initial
+        /// access token from `anz client registration-token`
+        #[arg(long)]
+        registration: Option<bool>,
+    },
     /// Delete a realm
     Delete {
         /// Realm name
@@ -33,9 +42,21 @@ pub fn handle(action: RealmAction, conn: &Connection) -> Result<()> {
             } else {
                 for r in realms {
                     println!("{:<20} {}", r.name, r.id);
+                    if r.registration_enabled {
+                        println!("  dynamic client registration enabled");
+                    }
                 }
             }
         }
+        RealmAction::Update { name, registration } => {
+            let Some(enabled) = registration else {
+                bail!("Nothing to update; pass --registration");
+            };
+            if !db::realm::set_registration_enabled(conn, &name, enabled)? {
+                bail!("Realm '{name}' not found");
+            }
+            println!("Updated realm '{name}'");
+        }
         RealmAction::Delete { name } => {
             if db::realm::delete_realm(conn, &name)? {
                 println!("Deleted realm '{name}'");
@@ -1,5 +1,6 @@
 use anyhow::Result;
 use rusqlite::Connection;
+use std::net::SocketAddr;
 
 use crate::config::Config;
 use crate::server;
@@ -8,10 +9,14 @@ pub fn run(config: Config, conn: Connection) -> Result<()> {
     let rt = tokio::runtime::Runtime::new()?;
     rt.block_on(async {
         let addr = config.bind_address.clone();
-        let app = server::build_router(config, conn);
+        let state = server::AppState::new(config, conn);
+        tokio::spawn(server::backchannel::run(state.clone()));
+        let app = server::build_router(state);
 
         tracing::info!("Listening on {addr}");
         let listener = tokio::net::TcpListener::bind(&addr).await?;
+        // Handlers see the peer address, e.g. to limit user code guesses
+        let app = app.into_make_service_with_connect_info::<SocketAddr>();
         axum::serve(listener, app).await?;
         Ok(())
     })
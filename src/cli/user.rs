@@ -27,6 +27,15 @@ pub enum UserAction {
         #[arg(long)]
         realm: String,
     },
+    /// End all of a user's sessions, notifying clients via back-channel logout
+    Logout {
+        /// Realm name
+        #[arg(long)]
+        realm: String,
+        /// Username
+        #[arg(long)]
+        username: String,
+    },
     /// Remove a user from a realm
     Remove {
         /// Realm name
@@ -87,6 +96,26 @@ pub fn handle(action: UserAction, conn: &Connection) -> Result<()> {
                 }
             }
         }
+        UserAction::Logout { realm, username } => {
+            let realm_obj = db::realm::get_realm_by_name(conn, &realm)?;
+            let realm_obj = match realm_obj {
+                Some(r) => r,
+                None => bail!("Realm '{realm}' not found"),
+            };
+            let Some(user) = db::user::get_user_by_username(conn, &realm_obj.id, &username)? else {
+                bail!("User '{username}' not found in realm '{realm}'");
+            };
+
+            let sessions = db::session::list_user_sessions(conn, &realm_obj.id, &user.id)?;
+            let mut queued = 0;
+            for session in &sessions {
+                queued += db::session::end_session(conn, session)?;
+            }
+            println!(
+                "Ended {} session(s) for '{username}'; {queued} back-channel logout(s) queued",
+                sessions.len()
+            );
+        }
         UserAction::Remove { realm, username } => {
             let realm_obj = db::realm::get_realm_by_name(conn, &realm)?;
             let realm_obj = match realm_obj {
@@ -2,6 +2,8 @@ use anyhow::{Context, Result};
 use serde::Deserialize;
 use std::path::Path;
 
+use crate::crypto::token::TokenHashAlg;
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     #[serde(default = "default_bind_address")]
@@ -27,6 +29,19 @@ pub struct Config {
 
     #[serde(default = "default_session_lifetime")]
     pub session_lifetime_secs: u64,
+
+    #[serde(default = "default_device_code_lifetime")]
+    pub device_code_lifetime_secs: u64,
+
+    #[serde(default = "default_device_poll_interval")]
+    pub device_poll_interval_secs: u64,
+
+    #[serde(default = "default_par_lifetime")]
+    pub par_lifetime_secs: u64,
+
+    /// Hash for `at_hash` and `c_hash` in ID tokens: `sha512` or `sha256`.
+    #[serde(default)]
+    pub id_token_hash_alg: TokenHashAlg,
 }
 
 fn default_bind_address() -> String {
@@ -53,6 +68,15 @@ fn default_auth_code_lifetime() -> u64 {
 fn default_session_lifetime() -> u64 {
     86400
 }
+fn default_device_code_lifetime() -> u64 {
+    600
+}
+fn default_device_poll_interval() -> u64 {
+    5
+}
+fn default_par_lifetime() -> u64 {
+    60
+}
 
 impl Config {
     pub fn load(path: &Path) -> Result<Self> {
@@ -88,6 +112,10 @@ impl Default for Config {
             refresh_token_lifetime_secs: default_refresh_token_lifetime(),
             auth_code_lifetime_secs: default_auth_code_lifetime(),
             session_lifetime_secs: default_session_lifetime(),
+            device_code_lifetime_secs: default_device_code_lifetime(),
+            device_poll_interval_secs: default_device_poll_interval(),
+            par_lifetime_secs: default_par_lifetime(),
+            id_token_hash_alg: TokenHashAlg::default(),
         }
     }
 }
@@ -0,0 +1,189 @@
+use anyhow::{anyhow, bail, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// How far a proof's `iat` may be from now. Proofs are single-use, so their
+/// `jti`s are remembered for this long too.
+pub const MAX_PROOF_AGE_SECS: i64 = 300;
+
+/// Algorithms accepted for proofs, as advertised in discovery.
+pub const SIGNING_ALGS: [Algorithm; 5] = [
+    Algorithm::EdDSA,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::RS256,
+    Algorithm::PS256,
+];
+
+/// A verified DPoP proof (RFC 9449 §4).
+#[derive(Debug)]
+pub struct DpopProof {
+    /// JWK SHA-256 thumbprint of the key that signed the proof.
+    pub jkt: String,
+    pub jti: String,
+    pub iat: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct DpopClaims {
+    jti: String,
+    htm: String,
+    htu: String,
+    iat: i64,
+    ath: Option<String>,
+}
+
+/// Verify a DPoP proof for a `method` request to `url`. Requests carrying an
+/// access token must have a proof bound to it through `ath`.
+pub fn verify_proof(
+    proof: &str,
+    method: &str,
+    url: &str,
+    access_token: Option<&str>,
+) -> Result<DpopProof> {
+    let raw_header = proof
+        .split('.')
+        .next()
+        .and_then(|h| URL_SAFE_NO_PAD.decode(h).ok())
+        .and_then(|h| serde_json::from_slice::<Value>(&h).ok())
+        .ok_or_else(|| anyhow!("malformed proof"))?;
+    if raw_header["typ"] != "dpop+jwt" {
+        bail!("proof typ must be dpop+jwt");
+    }
+    let jkt = jwk_thumbprint(&raw_header["jwk"])?;
+
+    let header = decode_header(proof)?;
+    if !SIGNING_ALGS.contains(&header.alg) {
+        bail!("unsupported proof algorithm");
+    }
+    let jwk = header.jwk.ok_or_else(|| anyhow!("proof has no jwk"))?;
+    let key = DecodingKey::from_jwk(&jwk)?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.validate_exp = false;
+    validation.required_spec_claims.clear();
+    let claims = decode::<DpopClaims>(proof, &key, &validation)?.claims;
+
+    if claims.htm != method {
+        bail!("proof htm does not match the request");
+    }
+    if strip_query(&claims.htu) != strip_query(url) {
+        bail!("proof htu does not match the request");
+    }
+    if Utc::now().timestamp().abs_diff(claims.iat) > MAX_PROOF_AGE_SECS as u64 {
+        bail!("proof iat is too far from now");
+    }
+    if let Some(token) = access_token {
+        let ath = URL_SAFE_NO_PAD.encode(Sha256::digest(token.as_bytes()));
+        if claims.ath.as_deref() != Some(ath.as_str()) {
+            bail!("proof ath does not match the access token");
+        }
+    }
+
+    Ok(DpopProof {
+        jkt,
+        jti: claims.jti,
+        iat: claims.iat,
+    })
+}
+
+/// RFC 7638 SHA-256 thumbprint of a public JWK: its required members, in
+/// lexicographic order, without whitespace.
+fn jwk_thumbprint(jwk: &Value) -> Result<String> {
+    if jwk.get("d").is_some() {
+        bail!("proof jwk must not contain a private key");
+    }
+    let members: &[&str] = match jwk["kty"].as_str() {
+        Some("OKP") => &["crv", "kty", "x"],
+        Some("EC") => &["crv", "kty", "x", "y"],
+        Some("RSA") => &["e", "kty", "n"],
+        _ => bail!("unsupported proof key type"),
+    };
+    let mut fields = Vec::new();
+    for member in members {
+        let value = jwk[*member]
+            .as_str()
+            .ok_or_else(|| anyhow!("proof jwk is missing '{member}'"))?;
+        fields.push(format!("\"{member}\":{}", serde_json::to_string(value)?));
+    }
+    let canonical = format!("{{{}}}", fields.join(","));
+    Ok(URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes())))
+}
+
+/// `htu` is compared without query and fragment (RFC 9449 §4.3).
+fn strip_query(url: &str) -> &str {
+    url.split(['?', '#']).next().unwrap_or(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::keys;
+    use jsonwebtoken::{encode, Header};
+    use serde_json::json;
+
+    const URL: &str = "https://auth.example/realms/r1/token";
+
+    /// A proof for `POST URL` signed with a fresh Ed25519 key, and the key's
+    /// thumbprint.
+    fn signed_proof(iat: i64) -> (String, String) {
+        let (private_pem, public_pem, kid) = keys::generate_ed25519_keypair().unwrap();
+        let jwk = keys::public_key_to_jwk(&public_pem, &kid).unwrap();
+        let mut header = Header::new(Algorithm::EdDSA);
+        header.typ = Some("dpop+jwt".to_string());
+        header.jwk = Some(serde_json::from_value(jwk.clone()).unwrap());
+        let claims = json!({ "jti": "proof-1", "htm": "POST", "htu": URL, "iat": iat });
+        let key = keys::encoding_key_from_pem(&private_pem).unwrap();
+        (
+            encode(&header, &claims, &key).unwrap(),
+            jwk_thumbprint(&jwk).unwrap(),
+        )
+    }
+
+    #[test]
+    fn thumbprint_matches_rfc7638_example() {
+        // RFC 7638 §3.1
+        let jwk = json!({
+            "kty": "RSA",
+            "n": "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw",
+            "e": "AQAB",
+            "alg": "RS256",
+            "kid": "2011-04-29",
+        });
+        assert_eq!(
+            jwk_thumbprint(&jwk).unwrap(),
+            "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+        );
+    }
+
+    #[test]
+    fn thumbprint_rejects_private_keys() {
+        let jwk = json!({ "kty": "OKP", "crv": "Ed25519", "x": "AAAA", "d": "AAAA" });
+        assert!(jwk_thumbprint(&jwk).is_err());
+    }
+
+    #[test]
+    fn accepts_fresh_proof() {
+        let (proof, jkt) = signed_proof(Utc::now().timestamp());
+        let verified = verify_proof(&proof, "POST", URL, None).unwrap();
+        assert_eq!(verified.jkt, jkt);
+        assert!(verify_proof(&proof, "GET", URL, None).is_err());
+        assert!(verify_proof(&proof, "POST", "https://other.example/token", None).is_err());
+    }
+
+    #[test]
+    fn rejects_proof_with_out_of_range_iat() {
+        for iat in [
+            i64::MIN,
+            i64::MAX,
+            Utc::now().timestamp() - MAX_PROOF_AGE_SECS - 1,
+        ] {
+            let (proof, _) = signed_proof(iat);
+            assert!(verify_proof(&proof, "POST", URL, None).is_err());
+        }
+    }
+}
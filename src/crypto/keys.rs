@@ -2,6 +2,7 @@ use anyhow::Result;
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use ed25519_dalek::pkcs8::{DecodePublicKey, EncodePrivateKey, EncodePublicKey};
 use ed25519_dalek::{SigningKey, VerifyingKey};
+use jsonwebtoken::jwk::{Jwk, JwkSet};
 use serde_json::{json, Value};
 
 /// Generate a new Ed25519 keypair. Returns (private_key_pem, public_key_pem, kid).
@@ -47,3 +48,15 @@ pub fn decoding_key_from_pem(public_key_pem: &str) -> Result<jsonwebtoken::Decod
     let key = jsonwebtoken::DecodingKey::from_ed_pem(public_key_pem.as_bytes())?;
     Ok(key)
 }
+
+/// Parse a client's registered public keys, given either as a JWKS
+/// (`{"keys": [...]}`) or as a single JWK.
+pub fn parse_jwks(json: &str) -> Result<JwkSet> {
+    let value: Value = serde_json::from_str(json)?;
+    if value.get("keys").is_some() {
+        Ok(serde_json::from_value(value)?)
+    } else {
+        let jwk: Jwk = serde_json::from_value(value)?;
+        Ok(JwkSet { keys: vec![jwk] })
+    }
+}
@@ -1,4 +1,5 @@
 pub mod csrf;
+pub mod dpop;
 pub mod keys;
 pub mod password;
 pub mod pkce;
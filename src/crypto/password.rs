@@ -1,6 +1,8 @@
 use anyhow::Result;
 use argon2::Argon2;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use password_hash::rand_core::OsRng;
+use password_hash::rand_core::RngCore;
 use password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
 
 /// Hash a password with Argon2id.
@@ -30,3 +32,10 @@ pub fn dummy_verify() {
         "$argon2id$v=19$m=19456,t=2,p=1$dW5rbm93bg$AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
     let _ = verify_password("dummy", dummy_hash);
 }
+
+/// Generate a random client secret (32 bytes, base64url-encoded).
+pub fn generate_client_secret() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
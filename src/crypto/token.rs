@@ -1,7 +1,24 @@
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use chrono::Utc;
-use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::dangerous::insecure_decode;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{
+    decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+
+/// Hash behind `at_hash` and `c_hash` (OIDC Core §3.1.3.6). The spec ties it
+/// to the signing algorithm; for Ed25519 that is SHA-512, but some RPs were
+/// written assuming SHA-256 for every EdDSA token.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenHashAlg {
+    #[default]
+    Sha512,
+    Sha256,
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct IdTokenClaims {
@@ -10,11 +27,37 @@ pub struct IdTokenClaims {
     pub aud: String,
     pub exp: i64,
     pub iat: i64,
+    /// Time the user authenticated (OIDC Core §2).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_time: Option<i64>,
+    /// Echoed from the authorization request to bind the token to it.
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub nonce: Option<String>,
-    pub preferred_username: String,
-    pub email: String,
+    /// Binds the ID token to the access token issued with it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub at_hash: Option<String>,
+    /// Binds the ID token to the authorization code it was exchanged for.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub c_hash: Option<String>,
+    /// Session the token was issued under, for back-channel logout.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    /// Only with the `profile` scope.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preferred_username: Option<String>,
+    /// Only with the `email` scope.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
 }
 
+/// `typ` header of access tokens (RFC 9068 §2.1), so that no other JWT we
+/// sign, such as an ID token, is accepted in their place.
+pub const ACCESS_TOKEN_TYP: &str = "at+jwt";
+
+/// `acr` of a sign-in with username and password: single-factor.
+pub const PASSWORD_ACR: &str = "1";
+
+/// Claims of a JWT access token (RFC 9068 §2.2).
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AccessTokenClaims {
     pub iss: String,
@@ -22,38 +65,220 @@ pub struct AccessTokenClaims {
     pub aud: String,
     pub exp: i64,
     pub iat: i64,
+    pub jti: String,
     pub scope: String,
     pub client_id: String,
+    /// Time the user authenticated; absent for client-only tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_time: Option<i64>,
+    /// How the user authenticated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub acr: Option<String>,
+    /// Only with the `profile` scope.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preferred_username: Option<String>,
+    /// Only with the `email` scope.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// Who is acting on the subject's behalf, for tokens obtained by
+    /// delegation through token exchange (RFC 8693 §4.1).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>,
+    /// Key the token is bound to, for DPoP (RFC 9449 §6).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cnf: Option<Confirmation>,
+}
+
+/// A `cnf` claim holding the SHA-256 thumbprint of a DPoP key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Confirmation {
+    pub jkt: String,
+}
+
+/// An `act` claim: the acting party, and any party that acted before it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Actor {
+    pub sub: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Box<Actor>>,
+}
+
+/// Claims of an OIDC back-channel logout token.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogoutTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: String,
+    pub iat: i64,
+    pub exp: i64,
+    pub jti: String,
+    pub sid: String,
+    pub events: serde_json::Value,
+}
+
+/// The claims of an `id_token_hint` needed to identify who is logging out.
+#[derive(Debug, Deserialize)]
+pub struct IdTokenHintClaims {
+    pub sub: String,
+    pub aud: String,
+}
+
+/// Claims of a `private_key_jwt` client assertion (RFC 7523 §3).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientAssertionClaims {
+    pub iss: String,
+    pub sub: String,
+    pub exp: i64,
+    pub jti: String,
 }
 
 pub fn encode_jwt(claims: &impl Serialize, kid: &str, key: &EncodingKey) -> Result<String> {
+    encode_jwt_with_typ(claims, "JWT", kid, key)
+}
+
+/// Encode a JWT with an explicit `typ` header, for token types that must
+/// not be mistaken for one another (e.g. `logout+jwt`).
+pub fn encode_jwt_with_typ(
+    claims: &impl Serialize,
+    typ: &str,
+    kid: &str,
+    key: &EncodingKey,
+) -> Result<String> {
     let mut header = Header::new(Algorithm::EdDSA);
+    header.typ = Some(typ.to_string());
     header.kid = Some(kid.to_string());
 
     let token = encode(&header, claims, key)?;
     Ok(token)
 }
 
+/// Encode an access token with the `at+jwt` type.
+pub fn encode_access_token(
+    claims: &AccessTokenClaims,
+    kid: &str,
+    key: &EncodingKey,
+) -> Result<String> {
+    encode_jwt_with_typ(claims, ACCESS_TOKEN_TYP, kid, key)
+}
+
+/// Verify an access token we issued. `audiences` lists the audiences the
+/// caller accepts: the realm issuer for our own endpoints, or the
+/// identifiers of the APIs tokens were minted for (RFC 8707).
 pub fn decode_access_token(
     token: &str,
     key: &DecodingKey,
     issuer: &str,
+    audiences: &[&str],
 ) -> Result<AccessTokenClaims> {
+    // RFC 9068 §4: `at+jwt`, optionally with its media type prefix
+    let typ = decode_header(token)?
+        .typ
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    if typ.strip_prefix("application/").unwrap_or(&typ) != ACCESS_TOKEN_TYP {
+        bail!("not an access token");
+    }
+
     let mut validation = Validation::new(Algorithm::EdDSA);
     validation.set_issuer(&[issuer]);
+    validation.set_audience(audiences);
     validation.set_required_spec_claims(&["exp", "iss", "sub"]);
 
     let data = decode::<AccessTokenClaims>(token, key, &validation)?;
     Ok(data.claims)
 }
 
+/// Verify an ID token we issued, passed back as `id_token_hint`. Expired
+/// tokens are accepted: the hint only identifies the session being ended.
+pub fn decode_id_token_hint(
+    token: &str,
+    key: &DecodingKey,
+    issuer: &str,
+) -> Result<IdTokenHintClaims> {
+    let mut validation = Validation::new(Algorithm::EdDSA);
+    validation.set_issuer(&[issuer]);
+    validation.validate_exp = false;
+    validation.validate_aud = false;
+    validation.set_required_spec_claims(&["iss", "sub", "aud"]);
+
+    let data = decode::<IdTokenHintClaims>(token, key, &validation)?;
+    Ok(data.claims)
+}
+
+/// Read the `sub` of a client assertion without verifying it, to find out
+/// which client's keys to verify it with.
+pub fn peek_assertion_subject(token: &str) -> Option<String> {
+    let data = insecure_decode::<serde_json::Value>(token).ok()?;
+    data.claims.get("sub")?.as_str().map(str::to_string)
+}
+
+/// Verify a client assertion against the client's registered keys.
+/// `iss` and `sub` must both be the client_id and `aud` one of `audiences`.
+pub fn decode_client_assertion(
+    token: &str,
+    jwks: &JwkSet,
+    client_id: &str,
+    audiences: &[String],
+) -> Result<ClientAssertionClaims> {
+    let (alg, key) = client_decoding_key(token, jwks)?;
+
+    let mut validation = Validation::new(alg);
+    validation.set_issuer(&[client_id]);
+    validation.sub = Some(client_id.to_string());
+    validation.set_audience(audiences);
+    validation.set_required_spec_claims(&["exp", "iss", "sub", "aud"]);
+
+    let data = decode::<ClientAssertionClaims>(token, &key, &validation)?;
+    Ok(data.claims)
+}
+
+/// Verify a signed authorization request object (RFC 9101) against the
+/// client's registered keys. `iss` must be the client_id and `aud` the
+/// issuer. Returns the claims, which carry the authorization parameters.
+pub fn decode_request_object(
+    token: &str,
+    jwks: &JwkSet,
+    client_id: &str,
+    issuer: &str,
+) -> Result<serde_json::Value> {
+    let (alg, key) = client_decoding_key(token, jwks)?;
+
+    let mut validation = Validation::new(alg);
+    validation.set_issuer(&[client_id]);
+    validation.set_audience(&[issuer]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+
+    let data = decode::<serde_json::Value>(token, &key, &validation)?;
+    Ok(data.claims)
+}
+
+/// Pick the client's key that signed `token`, by `kid` or as its only key.
+/// Symmetric algorithms are refused: clients have no shared signing secret.
+fn client_decoding_key(token: &str, jwks: &JwkSet) -> Result<(Algorithm, DecodingKey)> {
+    let header = decode_header(token)?;
+    if matches!(
+        header.alg,
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+    ) {
+        bail!("symmetric algorithms are not supported for client-signed JWTs");
+    }
+
+    let jwk = match &header.kid {
+        Some(kid) => jwks.find(kid),
+        None if jwks.keys.len() == 1 => jwks.keys.first(),
+        None => None,
+    }
+    .ok_or_else(|| anyhow!("no registered key matches the JWT"))?;
+    Ok((header.alg, DecodingKey::from_jwk(jwk)?))
+}
+
 pub fn build_id_token_claims(
     issuer: &str,
     sub: &str,
     aud: &str,
     lifetime_secs: u64,
-    username: &str,
-    email: &str,
+    username: Option<&str>,
+    email: Option<&str>,
     nonce: Option<String>,
 ) -> IdTokenClaims {
     let now = Utc::now().timestamp();
@@ -63,9 +288,50 @@ pub fn build_id_token_claims(
         aud: aud.to_string(),
         exp: now + lifetime_secs as i64,
         iat: now,
+        auth_time: None,
         nonce,
-        preferred_username: username.to_string(),
-        email: email.to_string(),
+        at_hash: None,
+        c_hash: None,
+        sid: None,
+        preferred_username: username.map(str::to_string),
+        email: email.map(str::to_string),
+    }
+}
+
+/// The left half of `value`'s hash, base64url-encoded, as used for
+/// `at_hash` and `c_hash`.
+pub fn token_hash(value: &str, alg: TokenHashAlg) -> String {
+    let digest = match alg {
+        TokenHashAlg::Sha512 => Sha512::digest(value.as_bytes()).to_vec(),
+        TokenHashAlg::Sha256 => Sha256::digest(value.as_bytes()).to_vec(),
+    };
+    URL_SAFE_NO_PAD.encode(&digest[..digest.len() / 2])
+}
+
+/// Lowercase hex, the form token and code hashes are stored in.
+pub fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub fn build_logout_token_claims(
+    issuer: &str,
+    sub: &str,
+    aud: &str,
+    sid: &str,
+    lifetime_secs: u64,
+) -> LogoutTokenClaims {
+    let now = Utc::now().timestamp();
+    LogoutTokenClaims {
+        iss: issuer.to_string(),
+        sub: sub.to_string(),
+        aud: aud.to_string(),
+        iat: now,
+        exp: now + lifetime_secs as i64,
+        jti: uuid::Uuid::new_v4().to_string(),
+        sid: sid.to_string(),
+        events: serde_json::json!({
+            "http://schemas.openid.net/event/backchannel-logout": {}
+        }),
     }
 }
 
@@ -84,7 +350,48 @@ pub fn build_access_token_claims(
         aud: aud.to_string(),
         exp: now + lifetime_secs as i64,
         iat: now,
+        jti: uuid::Uuid::new_v4().to_string(),
         scope: scope.to_string(),
         client_id: client_id.to_string(),
+        auth_time: None,
+        acr: None,
+        preferred_username: None,
+        email: None,
+        act: None,
+        cnf: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_hash_matches_oidc_examples() {
+        // OIDC Core §A.3 (at_hash) and §A.4 (c_hash), both RS256
+        assert_eq!(
+            token_hash(
+                "jHkWEdUXMU1BwAsC4vtUsZwnNvTIxEl0z9K3vx5KF0Y",
+                TokenHashAlg::Sha256
+            ),
+            "77QmUPtjPfzWtF2AnpK9RQ"
+        );
+        assert_eq!(
+            token_hash(
+                "Qcb0Orv1zh30vL1MPRsbm-diHiMwcLyZvn1arpZv-Jxf_11jnpEX3Tgfvk",
+                TokenHashAlg::Sha256
+            ),
+            "LDktKdoQak3Pk0cnXxCltA"
+        );
+    }
+
+    #[test]
+    fn token_hash_uses_left_half_of_sha512() {
+        let hash = token_hash(
+            "jHkWEdUXMU1BwAsC4vtUsZwnNvTIxEl0z9K3vx5KF0Y",
+            TokenHashAlg::Sha512,
+        );
+        assert_eq!(hash, "q7nS86GgvvFaZkzALLWqJYaJIKw2wCDAVfCAsm5CrBM");
+        assert_eq!(URL_SAFE_NO_PAD.decode(&hash).unwrap().len(), 32);
     }
 }
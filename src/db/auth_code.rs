@@ -12,6 +12,10 @@ pub struct NewAuthCode<'a> {
     pub redirect_uri: &'a str,
     pub scopes: &'a str,
     pub code_challenge: &'a str,
+    pub sid: &'a str,
+    pub nonce: Option<&'a str>,
+    pub auth_time: chrono::DateTime<Utc>,
+    pub resource: Option<&'a str>,
     pub expires_at: chrono::DateTime<Utc>,
 }
 
@@ -19,8 +23,8 @@ pub struct NewAuthCode<'a> {
 pub fn insert_auth_code(conn: &Connection, code: &NewAuthCode) -> Result<String> {
     let id = Uuid::new_v4().to_string();
     conn.execute(
-        "INSERT INTO authorization_codes (id, realm_id, client_id, user_id, code_hash, redirect_uri, scopes, code_challenge, expires_at, used)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, 0)",
+        "INSERT INTO authorization_codes (id, realm_id, client_id, user_id, code_hash, redirect_uri, scopes, code_challenge, sid, nonce, auth_time, resource, expires_at, used)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, 0)",
         params![
             id,
             code.realm_id,
@@ -30,23 +34,32 @@ pub fn insert_auth_code(conn: &Connection, code: &NewAuthCode) -> Result<String>
             code.redirect_uri,
             code.scopes,
             code.code_challenge,
+            code.sid,
+            code.nonce,
+            code.auth_time.to_rfc3339(),
+            code.resource,
             code.expires_at.to_rfc3339(),
         ],
     )?;
     Ok(id)
 }
 
-/// Consume an authorization code: look it up by hash, mark as used, return it.
-/// Returns None if not found, already used, or expired.
-pub fn consume_auth_code(conn: &Connection, code_hash: &str) -> Result<Option<AuthorizationCode>> {
+/// Look up an unused, unexpired authorization code by hash. Returns None if
+/// not found, already used, or expired.
+pub fn get_auth_code(
+    conn: &Connection,
+    realm_id: &str,
+    code_hash: &str,
+) -> Result<Option<AuthorizationCode>> {
     let now = Utc::now().to_rfc3339();
 
     let mut stmt = conn.prepare(
-        "SELECT id, client_id, user_id, redirect_uri, scopes, code_challenge
+        "SELECT id, client_id, user_id, redirect_uri, scopes, code_challenge, sid, nonce, auth_time, resource
          FROM authorization_codes
-         WHERE code_hash = ?1 AND used = 0 AND expires_at > ?2",
+         WHERE realm_id = ?1 AND code_hash = ?2 AND used = 0 AND expires_at > ?3",
     )?;
-    let mut rows = stmt.query_map(params![code_hash, now], |row| {
+    let mut rows = stmt.query_map(params![realm_id, code_hash, now], |row| {
+        let auth_time: Option<String> = row.get(8)?;
         Ok(AuthorizationCode {
             id: row.get(0)?,
             client_id: row.get(1)?,
@@ -54,19 +67,29 @@ pub fn consume_auth_code(conn: &Connection, code_hash: &str) -> Result<Option<Au
             redirect_uri: row.get(3)?,
             scopes: row.get(4)?,
             code_challenge: row.get(5)?,
+            sid: row.get(6)?,
+            nonce: row.get(7)?,
+            auth_time: auth_time.and_then(|s| {
+                chrono::DateTime::parse_from_rfc3339(&s)
+                    .ok()
+                    .map(|d| d.with_timezone(&Utc))
+            }),
+            resource: row.get(9)?,
         })
     })?;
 
     match rows.next() {
-        Some(r) => {
-            let code = r?;
-            // Mark as used
-            conn.execute(
-                "UPDATE authorization_codes SET used = 1 WHERE id = ?1",
-                params![code.id],
-            )?;
-            Ok(Some(code))
-        }
+        Some(r) => Ok(Some(r?)),
         None => Ok(None),
     }
 }
+
+/// Consume an authorization code looked up with `get_auth_code`: mark it
+/// used, so it can't be exchanged again.
+pub fn consume_auth_code(conn: &Connection, code: &AuthorizationCode) -> Result<()> {
+    conn.execute(
+        "UPDATE authorization_codes SET used = 1 WHERE id = ?1",
+        params![code.id],
+    )?;
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use crate::db;
+use crate::models::{BackchannelLogout, Session};
+use anyhow::Result;
+use chrono::Utc;
+use rusqlite::{params, Connection};
+use uuid::Uuid;
+
+/// Queue a logout notification for every client with a back-channel logout
+/// URI that obtained a code or tokens under `session`. Returns the number queued.
+pub fn enqueue_session_logouts(conn: &Connection, session: &Session) -> Result<usize> {
+    let mut client_ids = Vec::new();
+    for client_id in db::session::list_session_client_ids(conn, session)? {
+        let client = db::client::get_client_by_client_id(conn, &session.realm_id, &client_id)?;
+        if client.is_some_and(|c| c.backchannel_logout_uri.is_some()) {
+            client_ids.push(client_id);
+        }
+    }
+
+    let now = Utc::now().to_rfc3339();
+    for client_id in &client_ids {
+        conn.execute(
+            "INSERT INTO backchannel_logouts (id, realm_id, client_id, user_id, sid, attempts, next_attempt_at, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, 0, ?6, ?6)",
+            params![
+                Uuid::new_v4().to_string(),
+                session.realm_id,
+                client_id,
+                session.user_id,
+                session.id,
+                now,
+            ],
+        )?;
+    }
+    Ok(client_ids.len())
+}
+
+/// Notifications whose next attempt is due. Notifications for clients that
+/// were removed or no longer have a back-channel logout URI are dropped.
+pub fn list_due_backchannel_logouts(
+    conn: &Connection,
+    limit: i64,
+) -> Result<Vec<BackchannelLogout>> {
+    conn.execute(
+        "DELETE FROM backchannel_logouts
+         WHERE NOT EXISTS (
+             SELECT 1 FROM clients c
+             WHERE c.realm_id = backchannel_logouts.realm_id
+               AND c.client_id = backchannel_logouts.client_id
+               AND c.backchannel_logout_uri IS NOT NULL
+         )",
+        [],
+    )?;
+
+    let now = Utc::now().to_rfc3339();
+    let mut stmt = conn.prepare(
+        "SELECT b.id, b.realm_id, r.name, b.client_id, c.backchannel_logout_uri, b.user_id, b.sid, b.attempts
+         FROM backchannel_logouts b
+         JOIN realms r ON r.id = b.realm_id
+         JOIN clients c ON c.realm_id = b.realm_id AND c.client_id = b.client_id
+         WHERE b.next_attempt_at <= ?1
+         ORDER BY b.next_attempt_at
+         LIMIT ?2",
+    )?;
+    let rows = stmt.query_map(params![now, limit], |row| {
+        Ok(BackchannelLogout {
+            id: row.get(0)?,
+            realm_id: row.get(1)?,
+            realm_name: row.get(2)?,
+            client_id: row.get(3)?,
+            backchannel_logout_uri: row.get(4)?,
+            user_id: row.get(5)?,
+            sid: row.get(6)?,
+            attempts: row.get(7)?,
+        })
+    })?;
+    let mut logouts = Vec::new();
+    for r in rows {
+        logouts.push(r?);
+    }
+    Ok(logouts)
+}
+
+/// Schedule another delivery attempt after a failure.
+pub fn reschedule_backchannel_logout(
+    conn: &Connection,
+    id: &str,
+    attempts: i64,
+    next_attempt_at: chrono::DateTime<Utc>,
+) -> Result<()> {
+    conn.execute(
+        "UPDATE backchannel_logouts SET attempts = ?1, next_attempt_at = ?2 WHERE id = ?3",
+        params![attempts, next_attempt_at.to_rfc3339(), id],
+    )?;
+    Ok(())
+}
+
+pub fn delete_backchannel_logout(conn: &Connection, id: &str) -> Result<()> {
+    conn.execute("DELETE FROM backchannel_logouts WHERE id = ?1", params![id])?;
+    Ok(())
+}
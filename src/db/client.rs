@@ -1,81 +1,124 @@
-use crate::models::Client;
+use crate::models::{Client, ClientType};
 use anyhow::Result;
 use chrono::Utc;
 use rusqlite::{params, Connection};
 use uuid::Uuid;
 
-pub fn create_client(
-    conn: &Connection,
-    realm_id: &str,
-    client_id: &str,
-    redirect_uris: &[String],
-) -> Result<Client> {
+const CLIENT_COLUMNS: &str =
+    "id, realm_id, client_id, client_type, client_secret_hash, jwks, jwks_path, \
+     redirect_uris, post_logout_redirect_uris, backchannel_logout_uri, frontchannel_logout_uri, \
+     display_name, first_party, require_par, request_uris, registration_token_hash, \
+     pairwise, sector_identifier, allowed_scopes, created_at, grant_types";
+
+pub struct NewClient<'a> {
+    pub realm_id: &'a str,
+    pub client_id: &'a str,
+    pub client_type: ClientType,
+    pub client_secret_hash: Option<&'a str>,
+    pub jwks: Option<&'a str>,
+    pub jwks_path: Option<&'a str>,
+    pub redirect_uris: &'a [String],
+    pub post_logout_redirect_uris: &'a [String],
+    pub request_uris: &'a [String],
+    pub backchannel_logout_uri: Option<&'a str>,
+    pub frontchannel_logout_uri: Option<&'a str>,
+    pub display_name: Option<&'a str>,
+    pub first_party: bool,
+    pub require_par: bool,
+    pub registration_token_hash: Option<&'a str>,
+    pub pairwise: bool,
+    pub sector_identifier: Option<&'a str>,
+    pub grant_types: Option<&'a [String]>,
+}
+
+/// Changes to a registered client; `None` fields are left as they are.
+#[derive(Default)]
+pub struct ClientUpdate<'a> {
+    pub redirect_uris: Option<&'a [String]>,
+    pub jwks: Option<&'a str>,
+    pub allowed_scopes: Option<&'a [String]>,
+    pub display_name: Option<&'a str>,
+    pub first_party: Option<bool>,
+    pub require_par: Option<bool>,
+    pub pairwise: Option<bool>,
+    pub sector_identifier: Option<&'a str>,
+    pub grant_types: Option<&'a [String]>,
+}
+
+pub fn create_client(conn: &Connection, new: &NewClient) -> Result<Client> {
     let id = Uuid::new_v4().to_string();
     let now = Utc::now();
-    let uris_json = serde_json::to_string(redirect_uris)?;
+    let uris_json = serde_json::to_string(new.redirect_uris)?;
+    let logout_uris_json = serde_json::to_string(new.post_logout_redirect_uris)?;
+    let request_uris_json = serde_json::to_string(new.request_uris)?;
     let scopes_json = serde_json::to_string(&["openid", "profile", "email"])?;
+    let grant_types_json = new.grant_types.map(serde_json::to_string).transpose()?;
 
     conn.execute(
-        "INSERT INTO clients (id, realm_id, client_id, redirect_uris, allowed_scopes, created_at)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        "INSERT INTO clients (id, realm_id, client_id, client_type, client_secret_hash, jwks, jwks_path, redirect_uris, post_logout_redirect_uris, backchannel_logout_uri, frontchannel_logout_uri, display_name, first_party, require_par, request_uris, registration_token_hash, pairwise, sector_identifier, allowed_scopes, created_at, grant_types)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21)",
         params![
             id,
-            realm_id,
-            client_id,
+            new.realm_id,
+            new.client_id,
+            new.client_type.as_str(),
+            new.client_secret_hash,
+            new.jwks,
+            new.jwks_path,
             uris_json,
+            logout_uris_json,
+            new.backchannel_logout_uri,
+            new.frontchannel_logout_uri,
+            new.display_name,
+            new.first_party,
+            new.require_par,
+            request_uris_json,
+            new.registration_token_hash,
+            new.pairwise,
+            new.sector_identifier,
             scopes_json,
-            now.to_rfc3339()
+            now.to_rfc3339(),
+            grant_types_json
         ],
     )?;
 
     Ok(Client {
         id,
-        realm_id: realm_id.to_string(),
-        client_id: client_id.to_string(),
-        redirect_uris: redirect_uris.to_vec(),
+        realm_id: new.realm_id.to_string(),
+        client_id: new.client_id.to_string(),
+        client_type: new.client_type,
+        client_secret_hash: new.client_secret_hash.map(str::to_string),
+        jwks: new.jwks.map(str::to_string),
+        jwks_path: new.jwks_path.map(str::to_string),
+        redirect_uris: new.redirect_uris.to_vec(),
+        post_logout_redirect_uris: new.post_logout_redirect_uris.to_vec(),
+        request_uris: new.request_uris.to_vec(),
+        backchannel_logout_uri: new.backchannel_logout_uri.map(str::to_string),
+        frontchannel_logout_uri: new.frontchannel_logout_uri.map(str::to_string),
+        display_name: new.display_name.map(str::to_string),
+        first_party: new.first_party,
+        require_par: new.require_par,
+        registration_token_hash: new.registration_token_hash.map(str::to_string),
+        pairwise: new.pairwise,
+        sector_identifier: new.sector_identifier.map(str::to_string),
         allowed_scopes: vec![
             "openid".to_string(),
             "profile".to_string(),
             "email".to_string(),
         ],
         created_at: now,
+        grant_types: new.grant_types.map(<[String]>::to_vec),
     })
 }
 
 pub fn list_clients(conn: &Connection, realm_id: &str) -> Result<Vec<Client>> {
-    let mut stmt = conn.prepare(
-        "SELECT id, realm_id, client_id, redirect_uris, allowed_scopes, created_at
-         FROM clients WHERE realm_id = ?1 ORDER BY client_id",
-    )?;
-    let rows = stmt.query_map(params![realm_id], |row| {
-        let uris_json: String = row.get(3)?;
-        let scopes_json: String = row.get(4)?;
-        let created_str: String = row.get(5)?;
-        Ok((
-            row.get::<_, String>(0)?,
-            row.get::<_, String>(1)?,
-            row.get::<_, String>(2)?,
-            uris_json,
-            scopes_json,
-            created_str,
-        ))
-    })?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {CLIENT_COLUMNS} FROM clients WHERE realm_id = ?1 ORDER BY client_id"
+    ))?;
+    let rows = stmt.query_map(params![realm_id], row_to_client)?;
     let mut clients = Vec::new();
     for r in rows {
-        let (id, realm_id, client_id, uris_json, scopes_json, created_str) = r?;
-        let redirect_uris: Vec<String> = serde_json::from_str(&uris_json).unwrap_or_default();
-        let allowed_scopes: Vec<String> = serde_json::from_str(&scopes_json).unwrap_or_default();
-        let created_at = chrono::DateTime::parse_from_rfc3339(&created_str)
-            .unwrap_or_default()
-            .with_timezone(&Utc);
-        clients.push(Client {
-            id,
-            realm_id,
-            client_id,
-            redirect_uris,
-            allowed_scopes,
-            created_at,
-        });
+        clients.push(r?);
     }
     Ok(clients)
 }
@@ -85,45 +128,61 @@ pub fn get_client_by_client_id(
     realm_id: &str,
     client_id: &str,
 ) -> Result<Option<Client>> {
-    let mut stmt = conn.prepare(
-        "SELECT id, realm_id, client_id, redirect_uris, allowed_scopes, created_at
-         FROM clients WHERE realm_id = ?1 AND client_id = ?2",
-    )?;
-    let mut rows = stmt.query_map(params![realm_id, client_id], |row| {
-        let uris_json: String = row.get(3)?;
-        let scopes_json: String = row.get(4)?;
-        let created_str: String = row.get(5)?;
-        Ok((
-            row.get::<_, String>(0)?,
-            row.get::<_, String>(1)?,
-            row.get::<_, String>(2)?,
-            uris_json,
-            scopes_json,
-            created_str,
-        ))
-    })?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {CLIENT_COLUMNS} FROM clients WHERE realm_id = ?1 AND client_id = ?2"
+    ))?;
+    let mut rows = stmt.query_map(params![realm_id, client_id], row_to_client)?;
     match rows.next() {
-        Some(r) => {
-            let (id, realm_id, client_id, uris_json, scopes_json, created_str) = r?;
-            let redirect_uris: Vec<String> = serde_json::from_str(&uris_json).unwrap_or_default();
-            let allowed_scopes: Vec<String> =
-                serde_json::from_str(&scopes_json).unwrap_or_default();
-            let created_at = chrono::DateTime::parse_from_rfc3339(&created_str)
-                .unwrap_or_default()
-                .with_timezone(&Utc);
-            Ok(Some(Client {
-                id,
-                realm_id,
-                client_id,
-                redirect_uris,
-                allowed_scopes,
-                created_at,
-            }))
-        }
+        Some(r) => Ok(Some(r?)),
         None => Ok(None),
     }
 }
 
+/// Apply `update` to a client. Returns false if the client doesn't exist.
+pub fn update_client(
+    conn: &Connection,
+    realm_id: &str,
+    client_id: &str,
+    update: &ClientUpdate,
+) -> Result<bool> {
+    let uris_json = update
+        .redirect_uris
+        .map(serde_json::to_string)
+        .transpose()?;
+    let scopes_json = update
+        .allowed_scopes
+        .map(serde_json::to_string)
+        .transpose()?;
+    let grant_types_json = update.grant_types.map(serde_json::to_string).transpose()?;
+    let rows = conn.execute(
+        "UPDATE clients SET
+             redirect_uris = COALESCE(?1, redirect_uris),
+             jwks = COALESCE(?2, jwks),
+             allowed_scopes = COALESCE(?3, allowed_scopes),
+             display_name = COALESCE(?4, display_name),
+             first_party = COALESCE(?5, first_party),
+             require_par = COALESCE(?6, require_par),
+             pairwise = COALESCE(?7, pairwise),
+             sector_identifier = COALESCE(?8, sector_identifier),
+             grant_types = COALESCE(?9, grant_types)
+         WHERE realm_id = ?10 AND client_id = ?11",
+        params![
+            uris_json,
+            update.jwks,
+            scopes_json,
+            update.display_name,
+            update.first_party,
+            update.require_par,
+            update.pairwise,
+            update.sector_identifier,
+            grant_types_json,
+            realm_id,
+            client_id
+        ],
+    )?;
+    Ok(rows > 0)
+}
+
 pub fn delete_client(conn: &Connection, realm_id: &str, client_id: &str) -> Result<bool> {
     let rows = conn.execute(
         "DELETE FROM clients WHERE realm_id = ?1 AND client_id = ?2",
@@ -131,3 +190,38 @@ pub fn delete_client(conn: &Connection, realm_id: &str, client_id: &str) -> Resu
     )?;
     Ok(rows > 0)
 }
+
+fn row_to_client(row: &rusqlite::Row) -> rusqlite::Result<Client> {
+    let client_type: String = row.get(3)?;
+    let uris_json: String = row.get(7)?;
+    let logout_uris_json: String = row.get(8)?;
+    let request_uris_json: String = row.get(14)?;
+    let scopes_json: String = row.get(18)?;
+    let created_str: String = row.get(19)?;
+    let grant_types_json: Option<String> = row.get(20)?;
+    Ok(Client {
+        id: row.get(0)?,
+        realm_id: row.get(1)?,
+        client_id: row.get(2)?,
+        client_type: ClientType::parse(&client_type),
+        client_secret_hash: row.get(4)?,
+        jwks: row.get(5)?,
+        jwks_path: row.get(6)?,
+        redirect_uris: serde_json::from_str(&uris_json).unwrap_or_default(),
+        post_logout_redirect_uris: serde_json::from_str(&logout_uris_json).unwrap_or_default(),
+        request_uris: serde_json::from_str(&request_uris_json).unwrap_or_default(),
+        backchannel_logout_uri: row.get(9)?,
+        frontchannel_logout_uri: row.get(10)?,
+        display_name: row.get(11)?,
+        first_party: row.get(12)?,
+        require_par: row.get(13)?,
+        registration_token_hash: row.get(15)?,
+        pairwise: row.get(16)?,
+        sector_identifier: row.get(17)?,
+        allowed_scopes: serde_json::from_str(&scopes_json).unwrap_or_default(),
+        created_at: chrono::DateTime::parse_from_rfc3339(&created_str)
+            .unwrap_or_default()
+            .with_timezone(&Utc),
+        grant_types: grant_types_json.and_then(|g| serde_json::from_str(&g).ok()),
+    })
+}
@@ -0,0 +1,48 @@
+use anyhow::Result;
+use chrono::Utc;
+use rusqlite::{params, Connection};
+
+/// Scopes the user has already granted the client, space-delimited.
+pub fn get_consented_scopes(
+    conn: &Connection,
+    realm_id: &str,
+    user_id: &str,
+    client_id: &str,
+) -> Result<Option<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT scopes FROM consents WHERE realm_id = ?1 AND user_id = ?2 AND client_id = ?3",
+    )?;
+    let mut rows = stmt.query_map(params![realm_id, user_id, client_id], |row| row.get(0))?;
+    match rows.next() {
+        Some(r) => Ok(Some(r?)),
+        None => Ok(None),
+    }
+}
+
+/// Record that the user granted `scopes` to the client, adding to any
+/// earlier grant.
+pub fn grant_consent(
+    conn: &Connection,
+    realm_id: &str,
+    user_id: &str,
+    client_id: &str,
+    scopes: &str,
+) -> Result<()> {
+    let existing = get_consented_scopes(conn, realm_id, user_id, client_id)?.unwrap_or_default();
+    let mut merged: Vec<&str> = existing.split_whitespace().collect();
+    for scope in scopes.split_whitespace() {
+        if !merged.contains(&scope) {
+            merged.push(scope);
+        }
+    }
+
+    let now = Utc::now().to_rfc3339();
+    conn.execute(
+        "INSERT INTO consents (realm_id, user_id, client_id, scopes, created_at, updated_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?5)
+         ON CONFLICT (realm_id, user_id, client_id)
+         DO UPDATE SET scopes = excluded.scopes, updated_at = excluded.updated_at",
+        params![realm_id, user_id, client_id, merged.join(" "), now],
+    )?;
+    Ok(())
+}
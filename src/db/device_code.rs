@@ -0,0 +1,179 @@
+use crate::models::{DeviceCode, DeviceCodeStatus, Session};
+use anyhow::Result;
+use chrono::Utc;
+use rusqlite::{params, Connection};
+use uuid::Uuid;
+
+pub struct NewDeviceCode<'a> {
+    pub realm_id: &'a str,
+    pub client_id: &'a str,
+    pub device_code_hash: &'a str,
+    pub user_code: &'a str,
+    pub scopes: &'a str,
+    pub interval_secs: i64,
+    pub expires_at: chrono::DateTime<Utc>,
+}
+
+/// Insert a pending device authorization (storing the SHA-256 hash of the device code).
+pub fn insert_device_code(conn: &Connection, code: &NewDeviceCode) -> Result<String> {
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now().to_rfc3339();
+    conn.execute(
+        "DELETE FROM device_codes WHERE expires_at <= ?1",
+        params![now],
+    )?;
+    conn.execute(
+        "INSERT INTO device_codes (id, realm_id, client_id, device_code_hash, user_code, scopes, status, interval_secs, expires_at, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, 'pending', ?7, ?8, ?9)",
+        params![
+            id,
+            code.realm_id,
+            code.client_id,
+            code.device_code_hash,
+            code.user_code,
+            code.scopes,
+            code.interval_secs,
+            code.expires_at.to_rfc3339(),
+            now,
+        ],
+    )?;
+    Ok(id)
+}
+
+/// Look up a pending, unexpired device authorization by its user code.
+pub fn get_pending_by_user_code(
+    conn: &Connection,
+    realm_id: &str,
+    user_code: &str,
+) -> Result<Option<DeviceCode>> {
+    let now = Utc::now().to_rfc3339();
+    let mut stmt = conn.prepare(
+        "SELECT id, client_id, scopes, status, user_id, sid, auth_time, interval_secs, last_polled_at, expires_at
+         FROM device_codes
+         WHERE realm_id = ?1 AND user_code = ?2 AND status = 'pending' AND expires_at > ?3",
+    )?;
+    let mut rows = stmt.query_map(params![realm_id, user_code, now], row_to_device_code)?;
+    match rows.next() {
+        Some(r) => Ok(Some(r?)),
+        None => Ok(None),
+    }
+}
+
+/// Look up a device authorization by device code hash, including expired ones
+/// so the poller can be told `expired_token`.
+pub fn get_device_code_by_hash(
+    conn: &Connection,
+    realm_id: &str,
+    device_code_hash: &str,
+) -> Result<Option<DeviceCode>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, client_id, scopes, status, user_id, sid, auth_time, interval_secs, last_polled_at, expires_at
+         FROM device_codes
+         WHERE realm_id = ?1 AND device_code_hash = ?2",
+    )?;
+    let mut rows = stmt.query_map(params![realm_id, device_code_hash], row_to_device_code)?;
+    match rows.next() {
+        Some(r) => Ok(Some(r?)),
+        None => Ok(None),
+    }
+}
+
+/// Record the decision made in `session` on a pending device authorization.
+pub fn set_device_code_status(
+    conn: &Connection,
+    id: &str,
+    status: DeviceCodeStatus,
+    session: &Session,
+) -> Result<()> {
+    conn.execute(
+        "UPDATE device_codes SET status = ?1, user_id = ?2, sid = ?3, auth_time = ?4
+         WHERE id = ?5 AND status = 'pending'",
+        params![
+            status.as_str(),
+            session.user_id,
+            session.id,
+            session.auth_time.to_rfc3339(),
+            id
+        ],
+    )?;
+    Ok(())
+}
+
+/// Record a token endpoint poll and the interval the client must now respect.
+pub fn record_poll(
+    conn: &Connection,
+    id: &str,
+    polled_at: chrono::DateTime<Utc>,
+    interval_secs: i64,
+) -> Result<()> {
+    conn.execute(
+        "UPDATE device_codes SET last_polled_at = ?1, interval_secs = ?2 WHERE id = ?3",
+        params![polled_at.to_rfc3339(), interval_secs, id],
+    )?;
+    Ok(())
+}
+
+pub fn delete_device_code(conn: &Connection, id: &str) -> Result<()> {
+    conn.execute("DELETE FROM device_codes WHERE id = ?1", params![id])?;
+    Ok(())
+}
+
+/// Record a user code, entered from `client_ip`, that matched no pending
+/// device authorization.
+pub fn record_user_code_failure(conn: &Connection, realm_id: &str, client_ip: &str) -> Result<()> {
+    conn.execute(
+        "INSERT INTO user_code_failures (realm_id, client_ip, failed_at) VALUES (?1, ?2, ?3)",
+        params![realm_id, client_ip, Utc::now().to_rfc3339()],
+    )?;
+    Ok(())
+}
+
+/// Count the failed user codes entered from `client_ip` since `since`,
+/// forgetting older failures.
+pub fn count_user_code_failures(
+    conn: &Connection,
+    realm_id: &str,
+    client_ip: &str,
+    since: chrono::DateTime<Utc>,
+) -> Result<i64> {
+    let since = since.to_rfc3339();
+    conn.execute(
+        "DELETE FROM user_code_failures WHERE failed_at <= ?1",
+        params![since],
+    )?;
+    let count = conn.query_row(
+        "SELECT COUNT(*) FROM user_code_failures WHERE realm_id = ?1 AND client_ip = ?2",
+        params![realm_id, client_ip],
+        |row| row.get(0),
+    )?;
+    Ok(count)
+}
+
+fn row_to_device_code(row: &rusqlite::Row) -> rusqlite::Result<DeviceCode> {
+    let status: String = row.get(3)?;
+    let auth_time: Option<String> = row.get(6)?;
+    let last_polled: Option<String> = row.get(8)?;
+    let expires_str: String = row.get(9)?;
+    Ok(DeviceCode {
+        id: row.get(0)?,
+        client_id: row.get(1)?,
+        scopes: row.get(2)?,
+        status: DeviceCodeStatus::parse(&status),
+        user_id: row.get(4)?,
+        sid: row.get(5)?,
+        auth_time: auth_time.and_then(|s| {
+            chrono::DateTime::parse_from_rfc3339(&s)
+                .ok()
+                .map(|d| d.with_timezone(&Utc))
+        }),
+        interval_secs: row.get(7)?,
+        last_polled_at: last_polled.and_then(|s| {
+            chrono::DateTime::parse_from_rfc3339(&s)
+                .ok()
+                .map(|d| d.with_timezone(&Utc))
+        }),
+        expires_at: chrono::DateTime::parse_from_rfc3339(&expires_str)
+            .unwrap_or_default()
+            .with_timezone(&Utc),
+    })
+}
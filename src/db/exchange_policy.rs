@@ -0,0 +1,64 @@
+use anyhow::Result;
+use rusqlite::{params, Connection};
+
+/// Let `client_id` exchange user tokens for tokens to the API `audience`.
+pub fn allow_token_exchange(
+    conn: &Connection,
+    realm_id: &str,
+    client_id: &str,
+    audience: &str,
+) -> Result<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO token_exchange_policies (realm_id, client_id, audience)
+         VALUES (?1, ?2, ?3)",
+        params![realm_id, client_id, audience],
+    )?;
+    Ok(())
+}
+
+pub fn disallow_token_exchange(
+    conn: &Connection,
+    realm_id: &str,
+    client_id: &str,
+    audience: &str,
+) -> Result<bool> {
+    let rows = conn.execute(
+        "DELETE FROM token_exchange_policies
+         WHERE realm_id = ?1 AND client_id = ?2 AND audience = ?3",
+        params![realm_id, client_id, audience],
+    )?;
+    Ok(rows > 0)
+}
+
+pub fn is_token_exchange_allowed(
+    conn: &Connection,
+    realm_id: &str,
+    client_id: &str,
+    audience: &str,
+) -> Result<bool> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM token_exchange_policies
+         WHERE realm_id = ?1 AND client_id = ?2 AND audience = ?3",
+        params![realm_id, client_id, audience],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+/// Clients allowed to exchange tokens toward `audience`.
+pub fn list_exchange_clients(
+    conn: &Connection,
+    realm_id: &str,
+    audience: &str,
+) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT client_id FROM token_exchange_policies
+         WHERE realm_id = ?1 AND audience = ?2 ORDER BY client_id",
+    )?;
+    let rows = stmt.query_map(params![realm_id, audience], |row| row.get(0))?;
+    let mut clients = Vec::new();
+    for c in rows {
+        clients.push(c?);
+    }
+    Ok(clients)
+}
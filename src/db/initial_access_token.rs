@@ -0,0 +1,49 @@
+use anyhow::Result;
+use chrono::Utc;
+use rusqlite::{params, Connection};
+use uuid::Uuid;
+
+/// Store an initial access token for dynamic client registration, good for
+/// `uses` registrations until `expires_at`.
+pub fn create_initial_access_token(
+    conn: &Connection,
+    realm_id: &str,
+    token_hash: &str,
+    uses: u32,
+    expires_at: chrono::DateTime<Utc>,
+) -> Result<String> {
+    let id = Uuid::new_v4().to_string();
+    conn.execute(
+        "INSERT INTO initial_access_tokens (id, realm_id, token_hash, uses_remaining, expires_at, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            id,
+            realm_id,
+            token_hash,
+            uses,
+            expires_at.to_rfc3339(),
+            Utc::now().to_rfc3339(),
+        ],
+    )?;
+    Ok(id)
+}
+
+/// Spend one use of an initial access token. Returns false if the token is
+/// unknown, expired or used up.
+pub fn use_initial_access_token(
+    conn: &Connection,
+    realm_id: &str,
+    token_hash: &str,
+) -> Result<bool> {
+    let now = Utc::now().to_rfc3339();
+    conn.execute(
+        "DELETE FROM initial_access_tokens WHERE expires_at <= ?1 OR uses_remaining <= 0",
+        params![now],
+    )?;
+    let rows = conn.execute(
+        "UPDATE initial_access_tokens SET uses_remaining = uses_remaining - 1
+         WHERE realm_id = ?1 AND token_hash = ?2 AND expires_at > ?3 AND uses_remaining > 0",
+        params![realm_id, token_hash, now],
+    )?;
+    Ok(rows > 0)
+}
@@ -0,0 +1,24 @@
+use anyhow::Result;
+use chrono::Utc;
+use rusqlite::{params, Connection};
+
+/// Record a JWT ID so the same token can't be replayed before it expires.
+/// Returns false if `jti` was already seen for this issuer.
+pub fn record_jti(
+    conn: &Connection,
+    realm_id: &str,
+    issuer: &str,
+    jti: &str,
+    expires_at: chrono::DateTime<Utc>,
+) -> Result<bool> {
+    conn.execute(
+        "DELETE FROM used_jtis WHERE expires_at <= ?1",
+        params![Utc::now().to_rfc3339()],
+    )?;
+    let rows = conn.execute(
+        "INSERT OR IGNORE INTO used_jtis (realm_id, issuer, jti, expires_at)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![realm_id, issuer, jti, expires_at.to_rfc3339()],
+    )?;
+    Ok(rows > 0)
+}
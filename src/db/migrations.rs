@@ -6,6 +6,8 @@ pub fn run_migrations(conn: &Connection) -> rusqlite::Result<()> {
         CREATE TABLE IF NOT EXISTS realms (
             id          TEXT PRIMARY KEY,
             name        TEXT NOT NULL UNIQUE,
+            registration_enabled INTEGER NOT NULL DEFAULT 0,
+            pairwise_salt TEXT NOT NULL DEFAULT (lower(hex(randomblob(32)))),
             created_at  TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
         );
 
@@ -34,9 +36,24 @@ pub fn run_migrations(conn: &Connection) -> rusqlite::Result<()> {
             id             TEXT PRIMARY KEY,
             realm_id       TEXT NOT NULL REFERENCES realms(id) ON DELETE CASCADE,
             client_id      TEXT NOT NULL,
+            client_type    TEXT NOT NULL DEFAULT 'public',
+            client_secret_hash TEXT,
+            jwks           TEXT,
+            jwks_path      TEXT,
             redirect_uris  TEXT NOT NULL DEFAULT '[]',
+            post_logout_redirect_uris TEXT NOT NULL DEFAULT '[]',
+            backchannel_logout_uri TEXT,
+            frontchannel_logout_uri TEXT,
+            display_name   TEXT,
+            first_party    INTEGER NOT NULL DEFAULT 1,
+            require_par    INTEGER NOT NULL DEFAULT 0,
+            request_uris   TEXT NOT NULL DEFAULT '[]',
+            registration_token_hash TEXT,
+            pairwise       INTEGER NOT NULL DEFAULT 0,
+            sector_identifier TEXT,
             allowed_scopes TEXT NOT NULL DEFAULT '[\"openid\", \"profile\", \"email\"]',
             created_at     TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+            grant_types    TEXT,
             UNIQUE(realm_id, client_id)
         );
 
@@ -49,6 +66,10 @@ pub fn run_migrations(conn: &Connection) -> rusqlite::Result<()> {
             redirect_uri   TEXT NOT NULL,
             scopes         TEXT NOT NULL DEFAULT 'openid',
             code_challenge TEXT NOT NULL,
+            sid            TEXT,
+            nonce          TEXT,
+            auth_time      TEXT,
+            resource       TEXT,
             expires_at     TEXT NOT NULL,
             used           INTEGER NOT NULL DEFAULT 0
         );
@@ -60,8 +81,14 @@ pub fn run_migrations(conn: &Connection) -> rusqlite::Result<()> {
             user_id    TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
             token_hash TEXT NOT NULL UNIQUE,
             scopes     TEXT NOT NULL DEFAULT 'openid',
+            sid        TEXT,
+            auth_time  TEXT,
+            family_id  TEXT,
+            resource   TEXT,
+            dpop_jkt   TEXT,
             expires_at TEXT NOT NULL,
-            revoked    INTEGER NOT NULL DEFAULT 0
+            revoked    INTEGER NOT NULL DEFAULT 0,
+            created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
         );
 
         CREATE TABLE IF NOT EXISTS sessions (
@@ -69,9 +96,189 @@ pub fn run_migrations(conn: &Connection) -> rusqlite::Result<()> {
             realm_id           TEXT NOT NULL REFERENCES realms(id) ON DELETE CASCADE,
             user_id            TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
             session_token_hash TEXT NOT NULL UNIQUE,
+            auth_time          TEXT,
             expires_at         TEXT NOT NULL,
             created_at         TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
         );
+
+        CREATE TABLE IF NOT EXISTS device_codes (
+            id               TEXT PRIMARY KEY,
+            realm_id         TEXT NOT NULL REFERENCES realms(id) ON DELETE CASCADE,
+            client_id        TEXT NOT NULL,
+            device_code_hash TEXT NOT NULL UNIQUE,
+            user_code        TEXT NOT NULL,
+            scopes           TEXT NOT NULL DEFAULT 'openid',
+            status           TEXT NOT NULL DEFAULT 'pending',
+            user_id          TEXT REFERENCES users(id) ON DELETE CASCADE,
+            sid              TEXT,
+            auth_time        TEXT,
+            interval_secs    INTEGER NOT NULL DEFAULT 5,
+            last_polled_at   TEXT,
+            expires_at       TEXT NOT NULL,
+            created_at       TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+        );
+
+        CREATE TABLE IF NOT EXISTS user_code_failures (
+            realm_id  TEXT NOT NULL REFERENCES realms(id) ON DELETE CASCADE,
+            client_ip TEXT NOT NULL,
+            failed_at TEXT NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS used_jtis (
+            realm_id   TEXT NOT NULL REFERENCES realms(id) ON DELETE CASCADE,
+            issuer     TEXT NOT NULL,
+            jti        TEXT NOT NULL,
+            expires_at TEXT NOT NULL,
+            PRIMARY KEY (realm_id, issuer, jti)
+        );
+
+        CREATE TABLE IF NOT EXISTS initial_access_tokens (
+            id             TEXT PRIMARY KEY,
+            realm_id       TEXT NOT NULL REFERENCES realms(id) ON DELETE CASCADE,
+            token_hash     TEXT NOT NULL UNIQUE,
+            uses_remaining INTEGER NOT NULL,
+            expires_at     TEXT NOT NULL,
+            created_at     TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+        );
+
+        CREATE TABLE IF NOT EXISTS pushed_requests (
+            id               TEXT PRIMARY KEY,
+            realm_id         TEXT NOT NULL REFERENCES realms(id) ON DELETE CASCADE,
+            client_id        TEXT NOT NULL,
+            request_uri_hash TEXT NOT NULL UNIQUE,
+            params           TEXT NOT NULL,
+            expires_at       TEXT NOT NULL,
+            created_at       TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+        );
+
+        CREATE TABLE IF NOT EXISTS resource_servers (
+            id         TEXT PRIMARY KEY,
+            realm_id   TEXT NOT NULL REFERENCES realms(id) ON DELETE CASCADE,
+            identifier TEXT NOT NULL,
+            name       TEXT,
+            scopes     TEXT NOT NULL DEFAULT '[]',
+            created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+            UNIQUE(realm_id, identifier)
+        );
+
+        CREATE TABLE IF NOT EXISTS token_exchange_policies (
+            realm_id  TEXT NOT NULL REFERENCES realms(id) ON DELETE CASCADE,
+            client_id TEXT NOT NULL,
+            audience  TEXT NOT NULL,
+            PRIMARY KEY (realm_id, client_id, audience)
+        );
+
+        CREATE TABLE IF NOT EXISTS pairwise_subjects (
+            realm_id TEXT NOT NULL REFERENCES realms(id) ON DELETE CASCADE,
+            sub      TEXT NOT NULL,
+            user_id  TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            PRIMARY KEY (realm_id, sub)
+        );
+
+        CREATE TABLE IF NOT EXISTS consents (
+            realm_id   TEXT NOT NULL REFERENCES realms(id) ON DELETE CASCADE,
+            user_id    TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            client_id  TEXT NOT NULL,
+            scopes     TEXT NOT NULL,
+            created_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+            updated_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+            PRIMARY KEY (realm_id, user_id, client_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS backchannel_logouts (
+            id              TEXT PRIMARY KEY,
+            realm_id        TEXT NOT NULL REFERENCES realms(id) ON DELETE CASCADE,
+            client_id       TEXT NOT NULL,
+            user_id         TEXT NOT NULL,
+            sid             TEXT NOT NULL,
+            attempts        INTEGER NOT NULL DEFAULT 0,
+            next_attempt_at TEXT NOT NULL,
+            created_at      TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now'))
+        );
         ",
-    )
+    )?;
+
+    // Columns added after the initial schema. `CREATE TABLE IF NOT EXISTS`
+    // leaves tables in older databases untouched, so add them here too.
+    add_column(
+        conn,
+        "refresh_tokens",
+        "created_at",
+        "TEXT NOT NULL DEFAULT '1970-01-01T00:00:00Z'",
+    )?;
+    add_column(
+        conn,
+        "clients",
+        "client_type",
+        "TEXT NOT NULL DEFAULT 'public'",
+    )?;
+    add_column(conn, "clients", "client_secret_hash", "TEXT")?;
+    add_column(conn, "clients", "jwks", "TEXT")?;
+    add_column(conn, "clients", "jwks_path", "TEXT")?;
+    add_column(
+        conn,
+        "clients",
+        "post_logout_redirect_uris",
+        "TEXT NOT NULL DEFAULT '[]'",
+    )?;
+    add_column(conn, "clients", "backchannel_logout_uri", "TEXT")?;
+    add_column(conn, "clients", "frontchannel_logout_uri", "TEXT")?;
+    add_column(conn, "clients", "display_name", "TEXT")?;
+    add_column(conn, "clients", "first_party", "INTEGER NOT NULL DEFAULT 1")?;
+    add_column(conn, "clients", "require_par", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column(
+        conn,
+        "clients",
+        "request_uris",
+        "TEXT NOT NULL DEFAULT '[]'",
+    )?;
+    add_column(conn, "clients", "registration_token_hash", "TEXT")?;
+    add_column(
+        conn,
+        "realms",
+        "registration_enabled",
+        "INTEGER NOT NULL DEFAULT 0",
+    )?;
+    add_column(conn, "authorization_codes", "sid", "TEXT")?;
+    add_column(conn, "refresh_tokens", "sid", "TEXT")?;
+    add_column(conn, "device_codes", "sid", "TEXT")?;
+    add_column(conn, "authorization_codes", "nonce", "TEXT")?;
+    add_column(conn, "authorization_codes", "auth_time", "TEXT")?;
+    add_column(conn, "refresh_tokens", "auth_time", "TEXT")?;
+    add_column(conn, "device_codes", "auth_time", "TEXT")?;
+    add_column(conn, "sessions", "auth_time", "TEXT")?;
+    add_column(conn, "refresh_tokens", "family_id", "TEXT")?;
+    add_column(conn, "clients", "pairwise", "INTEGER NOT NULL DEFAULT 0")?;
+    add_column(conn, "clients", "sector_identifier", "TEXT")?;
+    add_column(conn, "clients", "grant_types", "TEXT")?;
+    // ALTER TABLE can't add a column with a random default, so backfill
+    add_column(conn, "realms", "pairwise_salt", "TEXT")?;
+    conn.execute(
+        "UPDATE realms SET pairwise_salt = lower(hex(randomblob(32))) WHERE pairwise_salt IS NULL",
+        [],
+    )?;
+    add_column(conn, "authorization_codes", "resource", "TEXT")?;
+    add_column(conn, "refresh_tokens", "resource", "TEXT")?;
+    add_column(conn, "refresh_tokens", "dpop_jkt", "TEXT")?;
+
+    Ok(())
+}
+
+/// Add a column to an existing table unless it is already present.
+fn add_column(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({table})"))?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    if !columns.iter().any(|c| c == column) {
+        conn.execute_batch(&format!(
+            "ALTER TABLE {table} ADD COLUMN {column} {definition}"
+        ))?;
+    }
+    Ok(())
 }
@@ -1,8 +1,17 @@
 pub mod auth_code;
+pub mod backchannel_logout;
 pub mod client;
+pub mod consent;
+pub mod device_code;
+pub mod exchange_policy;
+pub mod initial_access_token;
+pub mod jti;
 pub mod migrations;
+pub mod pairwise_subject;
+pub mod pushed_request;
 pub mod realm;
 pub mod refresh_token;
+pub mod resource_server;
 pub mod session;
 pub mod signing_key;
 pub mod user;
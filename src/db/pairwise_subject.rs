@@ -0,0 +1,32 @@
+use anyhow::Result;
+use rusqlite::{params, Connection};
+
+/// Remember which user a pairwise `sub` stands for, so tokens carrying it can
+/// be traced back to the user.
+pub fn record_pairwise_subject(
+    conn: &Connection,
+    realm_id: &str,
+    sub: &str,
+    user_id: &str,
+) -> Result<()> {
+    conn.execute(
+        "INSERT OR IGNORE INTO pairwise_subjects (realm_id, sub, user_id) VALUES (?1, ?2, ?3)",
+        params![realm_id, sub, user_id],
+    )?;
+    Ok(())
+}
+
+/// The user a pairwise `sub` was issued for, if any.
+pub fn get_pairwise_user_id(
+    conn: &Connection,
+    realm_id: &str,
+    sub: &str,
+) -> Result<Option<String>> {
+    let mut stmt =
+        conn.prepare("SELECT user_id FROM pairwise_subjects WHERE realm_id = ?1 AND sub = ?2")?;
+    let mut rows = stmt.query_map(params![realm_id, sub], |row| row.get(0))?;
+    match rows.next() {
+        Some(r) => Ok(Some(r?)),
+        None => Ok(None),
+    }
+}
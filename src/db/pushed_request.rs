@@ -0,0 +1,72 @@
+use crate::models::PushedRequest;
+use anyhow::Result;
+use chrono::Utc;
+use rusqlite::{params, Connection};
+use uuid::Uuid;
+
+/// Store a pushed authorization request under the SHA-256 hash of its `request_uri`.
+pub fn insert_pushed_request(
+    conn: &Connection,
+    realm_id: &str,
+    client_id: &str,
+    request_uri_hash: &str,
+    params_json: &str,
+    expires_at: chrono::DateTime<Utc>,
+) -> Result<String> {
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now().to_rfc3339();
+    conn.execute(
+        "DELETE FROM pushed_requests WHERE expires_at <= ?1",
+        params![now],
+    )?;
+    conn.execute(
+        "INSERT INTO pushed_requests (id, realm_id, client_id, request_uri_hash, params, expires_at, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            id,
+            realm_id,
+            client_id,
+            request_uri_hash,
+            params_json,
+            expires_at.to_rfc3339(),
+            now,
+        ],
+    )?;
+    Ok(id)
+}
+
+/// Look up a pushed request by hash. Returns None if not found or expired.
+pub fn get_pushed_request(
+    conn: &Connection,
+    realm_id: &str,
+    request_uri_hash: &str,
+) -> Result<Option<PushedRequest>> {
+    let now = Utc::now().to_rfc3339();
+    let mut stmt = conn.prepare(
+        "SELECT id, client_id, params
+         FROM pushed_requests
+         WHERE realm_id = ?1 AND request_uri_hash = ?2 AND expires_at > ?3",
+    )?;
+    let mut rows = stmt.query_map(params![realm_id, request_uri_hash, now], |row| {
+        Ok(PushedRequest {
+            id: row.get(0)?,
+            client_id: row.get(1)?,
+            params: row.get(2)?,
+        })
+    })?;
+
+    match rows.next() {
+        Some(r) => Ok(Some(r?)),
+        None => Ok(None),
+    }
+}
+
+/// Consume a pushed request looked up with `get_pushed_request`: delete it,
+/// so each `request_uri` works once.
+pub fn consume_pushed_request(conn: &Connection, request: &PushedRequest) -> Result<()> {
+    conn.execute(
+        "DELETE FROM pushed_requests WHERE id = ?1",
+        params![request.id],
+    )?;
+    Ok(())
+}
@@ -1,18 +1,23 @@
 use crate::crypto::keys::generate_ed25519_keypair;
+use crate::crypto::token::hex_encode;
 use crate::models::Realm;
 use anyhow::Result;
 use chrono::Utc;
+use rand::RngCore;
 use rusqlite::{params, Connection};
 use uuid::Uuid;
 
-/// Create a realm and auto-generate an Ed25519 signing key.
+/// Create a realm and auto-generate an Ed25519 signing key and a salt for
+/// pairwise subject identifiers.
 pub fn create_realm(conn: &Connection, name: &str) -> Result<Realm> {
     let id = Uuid::new_v4().to_string();
     let now = Utc::now();
+    let mut salt = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut salt);
 
     conn.execute(
-        "INSERT INTO realms (id, name, created_at) VALUES (?1, ?2, ?3)",
-        params![id, name, now.to_rfc3339()],
+        "INSERT INTO realms (id, name, pairwise_salt, created_at) VALUES (?1, ?2, ?3, ?4)",
+        params![id, name, hex_encode(&salt), now.to_rfc3339()],
     )?;
 
     // Auto-generate signing key for the realm
@@ -27,23 +32,15 @@ pub fn create_realm(conn: &Connection, name: &str) -> Result<Realm> {
     Ok(Realm {
         id,
         name: name.to_string(),
+        registration_enabled: false,
         created_at: now,
     })
 }
 
 pub fn list_realms(conn: &Connection) -> Result<Vec<Realm>> {
-    let mut stmt = conn.prepare("SELECT id, name, created_at FROM realms ORDER BY name")?;
-    let rows = stmt.query_map([], |row| {
-        let created_str: String = row.get(2)?;
-        let created_at = chrono::DateTime::parse_from_rfc3339(&created_str)
-            .unwrap_or_default()
-            .with_timezone(&Utc);
-        Ok(Realm {
-            id: row.get(0)?,
-            name: row.get(1)?,
-            created_at,
-        })
-    })?;
+    let mut stmt = conn
+        .prepare("SELECT id, name, registration_enabled, created_at FROM realms ORDER BY name")?;
+    let rows = stmt.query_map([], row_to_realm)?;
     let mut realms = Vec::new();
     for r in rows {
         realms.push(r?);
@@ -52,25 +49,48 @@ pub fn list_realms(conn: &Connection) -> Result<Vec<Realm>> {
 }
 
 pub fn get_realm_by_name(conn: &Connection, name: &str) -> Result<Option<Realm>> {
-    let mut stmt = conn.prepare("SELECT id, name, created_at FROM realms WHERE name = ?1")?;
-    let mut rows = stmt.query_map(params![name], |row| {
-        let created_str: String = row.get(2)?;
-        let created_at = chrono::DateTime::parse_from_rfc3339(&created_str)
-            .unwrap_or_default()
-            .with_timezone(&Utc);
-        Ok(Realm {
-            id: row.get(0)?,
-            name: row.get(1)?,
-            created_at,
-        })
-    })?;
+    let mut stmt = conn
+        .prepare("SELECT id, name, registration_enabled, created_at FROM realms WHERE name = ?1")?;
+    let mut rows = stmt.query_map(params![name], row_to_realm)?;
     match rows.next() {
         Some(r) => Ok(Some(r?)),
         None => Ok(None),
     }
 }
 
+/// Turn dynamic client registration on or off. Returns false if the realm
+/// doesn't exist.
+pub fn set_registration_enabled(conn: &Connection, name: &str, enabled: bool) -> Result<bool> {
+    let rows = conn.execute(
+        "UPDATE realms SET registration_enabled = ?1 WHERE name = ?2",
+        params![enabled, name],
+    )?;
+    Ok(rows > 0)
+}
+
+/// The realm's secret salt for pairwise subject identifiers.
+pub fn get_pairwise_salt(conn: &Connection, realm_id: &str) -> Result<String> {
+    let salt = conn.query_row(
+        "SELECT pairwise_salt FROM realms WHERE id = ?1",
+        params![realm_id],
+        |row| row.get(0),
+    )?;
+    Ok(salt)
+}
+
 pub fn delete_realm(conn: &Connection, name: &str) -> Result<bool> {
     let rows = conn.execute("DELETE FROM realms WHERE name = ?1", params![name])?;
     Ok(rows > 0)
 }
+
+fn row_to_realm(row: &rusqlite::Row) -> rusqlite::Result<Realm> {
+    let created_str: String = row.get(3)?;
+    Ok(Realm {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        registration_enabled: row.get(2)?,
+        created_at: chrono::DateTime::parse_from_rfc3339(&created_str)
+            .unwrap_or_default()
+            .with_timezone(&Utc),
+    })
+}
@@ -4,62 +4,148 @@ use chrono::Utc;
 use rusqlite::{params, Connection};
 use uuid::Uuid;
 
+/// Tokens issued before families were tracked are a family of their own.
+const REFRESH_TOKEN_COLUMNS: &str =
+    "id, client_id, user_id, scopes, sid, auth_time, COALESCE(family_id, id), resource, dpop_jkt, expires_at, created_at";
+
+pub struct NewRefreshToken<'a> {
+    pub realm_id: &'a str,
+    pub client_id: &'a str,
+    pub user_id: &'a str,
+    pub token_hash: &'a str,
+    pub scopes: &'a str,
+    pub sid: Option<&'a str>,
+    pub auth_time: Option<chrono::DateTime<Utc>>,
+    /// The family of the token this one replaces; None starts a new family.
+    pub family_id: Option<&'a str>,
+    pub resource: Option<&'a str>,
+    pub dpop_jkt: Option<&'a str>,
+    pub expires_at: chrono::DateTime<Utc>,
+}
+
 /// Insert a new refresh token (storing the SHA-256 hash).
-pub fn insert_refresh_token(
-    conn: &Connection,
-    realm_id: &str,
-    client_id: &str,
-    user_id: &str,
-    token_hash: &str,
-    scopes: &str,
-    expires_at: chrono::DateTime<Utc>,
-) -> Result<String> {
+pub fn insert_refresh_token(conn: &Connection, token: &NewRefreshToken) -> Result<String> {
     let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    let family_id = token.family_id.unwrap_or(&id);
     conn.execute(
-        "INSERT INTO refresh_tokens (id, realm_id, client_id, user_id, token_hash, scopes, expires_at, revoked)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0)",
+        "INSERT INTO refresh_tokens (id, realm_id, client_id, user_id, token_hash, scopes, sid, auth_time, family_id, resource, dpop_jkt, expires_at, revoked, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, 0, ?13)",
         params![
             id,
-            realm_id,
-            client_id,
-            user_id,
-            token_hash,
-            scopes,
-            expires_at.to_rfc3339(),
+            token.realm_id,
+            token.client_id,
+            token.user_id,
+            token.token_hash,
+            token.scopes,
+            token.sid,
+            token.auth_time.map(|t| t.to_rfc3339()),
+            family_id,
+            token.resource,
+            token.dpop_jkt,
+            token.expires_at.to_rfc3339(),
+            now.to_rfc3339(),
         ],
     )?;
     Ok(id)
 }
 
-/// Consume a refresh token: look it up by hash, revoke it, return it.
-/// Returns None if not found, already revoked, or expired.
-pub fn consume_refresh_token(conn: &Connection, token_hash: &str) -> Result<Option<RefreshToken>> {
-    let now = Utc::now().to_rfc3339();
+/// Consume a refresh token looked up with `get_refresh_token`: revoke it,
+/// so it can't be used again once exchanged for a new one (rotation).
+pub fn consume_refresh_token(conn: &Connection, token: &RefreshToken) -> Result<()> {
+    conn.execute(
+        "UPDATE refresh_tokens SET revoked = 1 WHERE id = ?1",
+        params![token.id],
+    )?;
+    Ok(())
+}
 
-    let mut stmt = conn.prepare(
-        "SELECT id, client_id, user_id, scopes
+/// Handle a refresh token that `get_refresh_token` didn't find: if it was
+/// already revoked, it is being reused after rotation (or revocation), which
+/// means it leaked. Revoke every token in its family so neither the attacker
+/// nor the legitimate client can keep refreshing, and return the reused
+/// token. Returns None if the hash isn't a revoked token.
+pub fn revoke_reused_token_family(
+    conn: &Connection,
+    realm_id: &str,
+    token_hash: &str,
+) -> Result<Option<RefreshToken>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {REFRESH_TOKEN_COLUMNS}
          FROM refresh_tokens
-         WHERE token_hash = ?1 AND revoked = 0 AND expires_at > ?2",
+         WHERE realm_id = ?1 AND token_hash = ?2 AND revoked = 1"
+    ))?;
+    let mut rows = stmt.query_map(params![realm_id, token_hash], row_to_refresh_token)?;
+    let Some(token) = rows.next().transpose()? else {
+        return Ok(None);
+    };
+
+    conn.execute(
+        "UPDATE refresh_tokens SET revoked = 1
+         WHERE realm_id = ?1 AND COALESCE(family_id, id) = ?2",
+        params![realm_id, token.family_id],
     )?;
-    let mut rows = stmt.query_map(params![token_hash, now], |row| {
-        Ok(RefreshToken {
-            id: row.get(0)?,
-            client_id: row.get(1)?,
-            user_id: row.get(2)?,
-            scopes: row.get(3)?,
-        })
-    })?;
+    Ok(Some(token))
+}
+
+/// Revoke a refresh token by hash, but only if it was issued to `client_id`.
+/// Returns true if a live token was revoked.
+pub fn revoke_refresh_token(
+    conn: &Connection,
+    realm_id: &str,
+    client_id: &str,
+    token_hash: &str,
+) -> Result<bool> {
+    let rows = conn.execute(
+        "UPDATE refresh_tokens SET revoked = 1
+         WHERE realm_id = ?1 AND client_id = ?2 AND token_hash = ?3 AND revoked = 0",
+        params![realm_id, client_id, token_hash],
+    )?;
+    Ok(rows > 0)
+}
 
+/// Look up a live refresh token by hash without consuming it.
+pub fn get_refresh_token(
+    conn: &Connection,
+    realm_id: &str,
+    token_hash: &str,
+) -> Result<Option<RefreshToken>> {
+    let now = Utc::now().to_rfc3339();
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {REFRESH_TOKEN_COLUMNS}
+         FROM refresh_tokens
+         WHERE realm_id = ?1 AND token_hash = ?2 AND revoked = 0 AND expires_at > ?3"
+    ))?;
+    let mut rows = stmt.query_map(params![realm_id, token_hash, now], row_to_refresh_token)?;
     match rows.next() {
-        Some(r) => {
-            let token = r?;
-            // Revoke on use (rotation)
-            conn.execute(
-                "UPDATE refresh_tokens SET revoked = 1 WHERE id = ?1",
-                params![token.id],
-            )?;
-            Ok(Some(token))
-        }
+        Some(r) => Ok(Some(r?)),
         None => Ok(None),
     }
 }
+
+fn row_to_refresh_token(row: &rusqlite::Row) -> rusqlite::Result<RefreshToken> {
+    let auth_time: Option<String> = row.get(5)?;
+    let expires_str: String = row.get(9)?;
+    let created_str: String = row.get(10)?;
+    Ok(RefreshToken {
+        id: row.get(0)?,
+        client_id: row.get(1)?,
+        user_id: row.get(2)?,
+        scopes: row.get(3)?,
+        sid: row.get(4)?,
+        auth_time: auth_time.and_then(|s| {
+            chrono::DateTime::parse_from_rfc3339(&s)
+                .ok()
+                .map(|d| d.with_timezone(&Utc))
+        }),
+        family_id: row.get(6)?,
+        resource: row.get(7)?,
+        dpop_jkt: row.get(8)?,
+        expires_at: chrono::DateTime::parse_from_rfc3339(&expires_str)
+            .unwrap_or_default()
+            .with_timezone(&Utc),
+        created_at: chrono::DateTime::parse_from_rfc3339(&created_str)
+            .unwrap_or_default()
+            .with_timezone(&Utc),
+    })
+}
@@ -0,0 +1,95 @@
+use crate::models::ResourceServer;
+use anyhow::Result;
+use chrono::Utc;
+use rusqlite::{params, Connection};
+use uuid::Uuid;
+
+const RESOURCE_SERVER_COLUMNS: &str = "id, realm_id, identifier, name, scopes, created_at";
+
+pub fn create_resource_server(
+    conn: &Connection,
+    realm_id: &str,
+    identifier: &str,
+    name: Option<&str>,
+    scopes: &[String],
+) -> Result<ResourceServer> {
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now();
+    conn.execute(
+        "INSERT INTO resource_servers (id, realm_id, identifier, name, scopes, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            id,
+            realm_id,
+            identifier,
+            name,
+            serde_json::to_string(scopes)?,
+            now.to_rfc3339()
+        ],
+    )?;
+    Ok(ResourceServer {
+        id,
+        realm_id: realm_id.to_string(),
+        identifier: identifier.to_string(),
+        name: name.map(str::to_string),
+        scopes: scopes.to_vec(),
+        created_at: now,
+    })
+}
+
+pub fn list_resource_servers(conn: &Connection, realm_id: &str) -> Result<Vec<ResourceServer>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {RESOURCE_SERVER_COLUMNS} FROM resource_servers
+         WHERE realm_id = ?1 ORDER BY identifier"
+    ))?;
+    let rows = stmt.query_map(params![realm_id], row_to_resource_server)?;
+    let mut servers = Vec::new();
+    for s in rows {
+        servers.push(s?);
+    }
+    Ok(servers)
+}
+
+/// Look up an API by the identifier clients pass as `resource`.
+pub fn get_resource_server(
+    conn: &Connection,
+    realm_id: &str,
+    identifier: &str,
+) -> Result<Option<ResourceServer>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {RESOURCE_SERVER_COLUMNS} FROM resource_servers
+         WHERE realm_id = ?1 AND identifier = ?2"
+    ))?;
+    let mut rows = stmt.query_map(params![realm_id, identifier], row_to_resource_server)?;
+    match rows.next() {
+        Some(s) => Ok(Some(s?)),
+        None => Ok(None),
+    }
+}
+
+pub fn delete_resource_server(conn: &Connection, realm_id: &str, identifier: &str) -> Result<bool> {
+    conn.execute(
+        "DELETE FROM token_exchange_policies WHERE realm_id = ?1 AND audience = ?2",
+        params![realm_id, identifier],
+    )?;
+    let rows = conn.execute(
+        "DELETE FROM resource_servers WHERE realm_id = ?1 AND identifier = ?2",
+        params![realm_id, identifier],
+    )?;
+    Ok(rows > 0)
+}
+
+fn row_to_resource_server(row: &rusqlite::Row) -> rusqlite::Result<ResourceServer> {
+    let scopes_json: String = row.get(4)?;
+    let created_str: String = row.get(5)?;
+    Ok(ResourceServer {
+        id: row.get(0)?,
+        realm_id: row.get(1)?,
+        identifier: row.get(2)?,
+        name: row.get(3)?,
+        scopes: serde_json::from_str(&scopes_json).unwrap_or_default(),
+        created_at: chrono::DateTime::parse_from_rfc3339(&created_str)
+            .unwrap_or_default()
+            .with_timezone(&Utc),
+    })
+}
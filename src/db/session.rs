@@ -1,9 +1,14 @@
+use crate::db;
 use crate::models::Session;
 use anyhow::Result;
 use chrono::Utc;
 use rusqlite::{params, Connection};
 use uuid::Uuid;
 
+/// Sessions created before `auth_time` was tracked authenticated when they
+/// were created.
+const SESSION_COLUMNS: &str = "id, realm_id, user_id, COALESCE(auth_time, created_at)";
+
 pub fn create_session(
     conn: &Connection,
     realm_id: &str,
@@ -14,19 +19,22 @@ pub fn create_session(
     let id = Uuid::new_v4().to_string();
     let now = Utc::now();
     conn.execute(
-        "INSERT INTO sessions (id, realm_id, user_id, session_token_hash, expires_at, created_at)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        "INSERT INTO sessions (id, realm_id, user_id, session_token_hash, auth_time, expires_at, created_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?5)",
         params![
             id,
             realm_id,
             user_id,
             session_token_hash,
+            now.to_rfc3339(),
             expires_at.to_rfc3339(),
-            now.to_rfc3339()
         ],
     )?;
     Ok(Session {
+        id,
+        realm_id: realm_id.to_string(),
         user_id: user_id.to_string(),
+        auth_time: now,
     })
 }
 
@@ -36,18 +44,94 @@ pub fn get_session_by_token_hash(
     token_hash: &str,
 ) -> Result<Option<Session>> {
     let now = Utc::now().to_rfc3339();
-    let mut stmt = conn.prepare(
-        "SELECT user_id
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {SESSION_COLUMNS}
          FROM sessions
-         WHERE realm_id = ?1 AND session_token_hash = ?2 AND expires_at > ?3",
-    )?;
-    let mut rows = stmt.query_map(params![realm_id, token_hash, now], |row| {
-        Ok(Session {
-            user_id: row.get(0)?,
-        })
-    })?;
+         WHERE realm_id = ?1 AND session_token_hash = ?2 AND expires_at > ?3"
+    ))?;
+    let mut rows = stmt.query_map(params![realm_id, token_hash, now], row_to_session)?;
     match rows.next() {
         Some(s) => Ok(Some(s?)),
         None => Ok(None),
     }
 }
+
+/// Sessions that have passed their expiry but have not been ended yet.
+pub fn list_expired_sessions(conn: &Connection) -> Result<Vec<Session>> {
+    let now = Utc::now().to_rfc3339();
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {SESSION_COLUMNS} FROM sessions WHERE expires_at <= ?1"
+    ))?;
+    let rows = stmt.query_map(params![now], row_to_session)?;
+    let mut sessions = Vec::new();
+    for r in rows {
+        sessions.push(r?);
+    }
+    Ok(sessions)
+}
+
+/// All of a user's sessions, expired or not.
+pub fn list_user_sessions(
+    conn: &Connection,
+    realm_id: &str,
+    user_id: &str,
+) -> Result<Vec<Session>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {SESSION_COLUMNS} FROM sessions WHERE realm_id = ?1 AND user_id = ?2"
+    ))?;
+    let rows = stmt.query_map(params![realm_id, user_id], row_to_session)?;
+    let mut sessions = Vec::new();
+    for r in rows {
+        sessions.push(r?);
+    }
+    Ok(sessions)
+}
+
+/// Record that the session's user just authenticated again, keeping the
+/// session (and its sid) but moving its `auth_time` to now.
+pub fn reauthenticate_session(conn: &Connection, session: &Session) -> Result<Session> {
+    let now = Utc::now();
+    conn.execute(
+        "UPDATE sessions SET auth_time = ?1 WHERE id = ?2",
+        params![now.to_rfc3339(), session.id],
+    )?;
+    Ok(Session {
+        auth_time: now,
+        ..session.clone()
+    })
+}
+
+/// Clients that obtained a code or tokens under `session`.
+pub fn list_session_client_ids(conn: &Connection, session: &Session) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT client_id FROM authorization_codes WHERE realm_id = ?1 AND sid = ?2
+         UNION
+         SELECT client_id FROM device_codes WHERE realm_id = ?1 AND sid = ?2
+         UNION
+         SELECT client_id FROM refresh_tokens WHERE realm_id = ?1 AND sid = ?2",
+    )?;
+    let client_ids = stmt
+        .query_map(params![session.realm_id, session.id], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+    Ok(client_ids)
+}
+
+/// Delete a session and queue back-channel logout notifications for the
+/// clients that obtained tokens under it. Returns the number queued.
+pub fn end_session(conn: &Connection, session: &Session) -> Result<usize> {
+    let queued = db::backchannel_logout::enqueue_session_logouts(conn, session)?;
+    conn.execute("DELETE FROM sessions WHERE id = ?1", params![session.id])?;
+    Ok(queued)
+}
+
+fn row_to_session(row: &rusqlite::Row) -> rusqlite::Result<Session> {
+    let auth_time_str: String = row.get(3)?;
+    Ok(Session {
+        id: row.get(0)?,
+        realm_id: row.get(1)?,
+        user_id: row.get(2)?,
+        auth_time: chrono::DateTime::parse_from_rfc3339(&auth_time_str)
+            .unwrap_or_default()
+            .with_timezone(&Utc),
+    })
+}
@@ -25,6 +25,7 @@ fn main() -> Result<()> {
         cli::Commands::Realm { action } => cli::realm::handle(action, &conn)?,
         cli::Commands::User { action } => cli::user::handle(action, &conn)?,
         cli::Commands::Client { action } => cli::client::handle(action, &conn)?,
+        cli::Commands::Api { action } => cli::api::handle(action, &conn)?,
         cli::Commands::Serve => cli::serve::run(config, conn)?,
     }
 
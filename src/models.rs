@@ -5,6 +5,8 @@ use serde::{Deserialize, Serialize};
 pub struct Realm {
     pub id: String,
     pub name: String,
+    /// Clients may register themselves at `/register` (RFC 7591).
+    pub registration_enabled: bool,
     pub created_at: DateTime<Utc>,
 }
 
@@ -19,14 +21,85 @@ pub struct User {
     pub updated_at: DateTime<Utc>,
 }
 
+/// An API in a realm that access tokens can be minted for (RFC 8707).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceServer {
+    pub id: String,
+    pub realm_id: String,
+    /// Absolute URI clients pass as `resource`; becomes the token's `aud`.
+    pub identifier: String,
+    pub name: Option<String>,
+    /// Scopes the API understands; empty if it takes any.
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Public clients (SPAs, native apps) can't keep a secret; confidential
+/// clients authenticate at the token endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientType {
+    Public,
+    Confidential,
+}
+
+impl ClientType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClientType::Public => "public",
+            ClientType::Confidential => "confidential",
+        }
+    }
+
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "confidential" => ClientType::Confidential,
+            _ => ClientType::Public,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Client {
     pub id: String,
     pub realm_id: String,
     pub client_id: String,
+    pub client_type: ClientType,
+    #[serde(skip_serializing)]
+    pub client_secret_hash: Option<String>,
+    /// Inline JWK or JWKS used for `private_key_jwt` authentication.
+    pub jwks: Option<String>,
+    /// Path to a JWKS file, read on every authentication so keys can rotate.
+    pub jwks_path: Option<String>,
     pub redirect_uris: Vec<String>,
+    pub post_logout_redirect_uris: Vec<String>,
+    /// URLs the client may pass as `request_uri` to have a signed request
+    /// object fetched from (RFC 9101 §5.2).
+    pub request_uris: Vec<String>,
+    /// Where OIDC back-channel logout tokens are POSTed when a session ends.
+    pub backchannel_logout_uri: Option<String>,
+    /// Loaded in an iframe on the logout page (OIDC Front-Channel Logout).
+    pub frontchannel_logout_uri: Option<String>,
+    /// Name shown on the consent screen.
+    pub display_name: Option<String>,
+    /// First-party clients are trusted and skip the consent screen.
+    pub first_party: bool,
+    /// Authorization requests must be pushed to `/par` first (RFC 9126).
+    pub require_par: bool,
+    /// Hash of the token a dynamically registered client manages its
+    /// registration with (RFC 7592).
+    #[serde(skip_serializing)]
+    pub registration_token_hash: Option<String>,
+    /// Issue pairwise `sub` values instead of the user id (OIDC Core §8.1).
+    pub pairwise: bool,
+    /// Groups clients that share pairwise subjects; defaults to the host of
+    /// the redirect URIs.
+    pub sector_identifier: Option<String>,
     pub allowed_scopes: Vec<String>,
     pub created_at: DateTime<Utc>,
+    /// Grants the client registered for (RFC 7591 §2); `None` allows every
+    /// grant its client type supports.
+    pub grant_types: Option<Vec<String>>,
 }
 
 #[derive(Debug, Clone)]
@@ -37,6 +110,19 @@ pub struct AuthorizationCode {
     pub redirect_uri: String,
     pub scopes: String,
     pub code_challenge: String,
+    pub sid: Option<String>,
+    pub nonce: Option<String>,
+    pub auth_time: Option<DateTime<Utc>>,
+    /// API the access token is for (RFC 8707), if one was requested.
+    pub resource: Option<String>,
+}
+
+/// An authorization request pushed to `/par`, as JSON-encoded parameters.
+#[derive(Debug, Clone)]
+pub struct PushedRequest {
+    pub id: String,
+    pub client_id: String,
+    pub params: String,
 }
 
 #[derive(Debug, Clone)]
@@ -45,6 +131,55 @@ pub struct RefreshToken {
     pub client_id: String,
     pub user_id: String,
     pub scopes: String,
+    pub sid: Option<String>,
+    pub auth_time: Option<DateTime<Utc>>,
+    /// Shared by every token rotated from the same original grant.
+    pub family_id: String,
+    /// API the grant's access tokens are for, if one was requested.
+    pub resource: Option<String>,
+    /// DPoP key the token is bound to; refreshing needs a proof by it.
+    pub dpop_jkt: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceCodeStatus {
+    Pending,
+    Approved,
+    Denied,
+}
+
+impl DeviceCodeStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeviceCodeStatus::Pending => "pending",
+            DeviceCodeStatus::Approved => "approved",
+            DeviceCodeStatus::Denied => "denied",
+        }
+    }
+
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "approved" => DeviceCodeStatus::Approved,
+            "denied" => DeviceCodeStatus::Denied,
+            _ => DeviceCodeStatus::Pending,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DeviceCode {
+    pub id: String,
+    pub client_id: String,
+    pub scopes: String,
+    pub status: DeviceCodeStatus,
+    pub user_id: Option<String>,
+    pub sid: Option<String>,
+    pub auth_time: Option<DateTime<Utc>>,
+    pub interval_secs: i64,
+    pub last_polled_at: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone)]
@@ -56,5 +191,23 @@ pub struct SigningKeyRecord {
 
 #[derive(Debug, Clone)]
 pub struct Session {
+    /// Also the `sid` claim shared with the clients that took part in the session.
+    pub id: String,
+    pub realm_id: String,
+    pub user_id: String,
+    /// When the user authenticated to start the session.
+    pub auth_time: DateTime<Utc>,
+}
+
+/// A queued back-channel logout notification for one client.
+#[derive(Debug, Clone)]
+pub struct BackchannelLogout {
+    pub id: String,
+    pub realm_id: String,
+    pub realm_name: String,
+    pub client_id: String,
+    pub backchannel_logout_uri: String,
     pub user_id: String,
+    pub sid: String,
+    pub attempts: i64,
 }
@@ -1,30 +1,96 @@
 use askama::Template;
 use axum::extract::{Path, Query, State};
 use axum::http::header::SET_COOKIE;
-use axum::http::HeaderMap;
-use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{AppendHeaders, Html, IntoResponse, Response};
 use axum::Form;
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 use chrono::{Duration, Utc};
 use rand::RngCore;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer, Serialize};
 use sha2::{Digest, Sha256};
 
+use super::client_auth::client_jwks;
+use super::consent::{consent_required, render_consent};
 use super::error::AppError;
+use super::resource::find_resource;
+use super::response_mode::{authorization_response, validate_response_mode};
+use super::scope::{downscope, has_scope};
 use super::AppState;
-use crate::crypto::{csrf, password as pw};
+use crate::crypto::{csrf, password as pw, token as jwt};
 use crate::db;
+use crate::models::{Client, Session, User};
 
-#[derive(Debug, Deserialize)]
+/// Prefix of the `request_uri` values handed out by `/par`. Any other
+/// `request_uri` refers to a request object hosted by the client.
+pub(super) const PAR_REQUEST_URI_PREFIX: &str = "urn:ietf:params:oauth:request_uri:";
+
+/// Largest request object fetched from a client's `request_uri`.
+const MAX_REQUEST_OBJECT_BYTES: usize = 64 * 1024;
+
+/// How long a pushed request stays usable while the user signs in and
+/// consents.
+const SIGN_IN_LIFETIME_SECS: i64 = 600;
+
+#[derive(Debug, Deserialize, Serialize)]
 pub struct AuthorizeQuery {
+    /// Defaulted so a bare `client_id` + `request_uri` request deserializes;
+    /// the pushed request supplies the real values.
+    #[serde(default)]
     pub response_type: String,
     pub client_id: String,
+    #[serde(default)]
     pub redirect_uri: String,
     pub scope: Option<String>,
     pub state: Option<String>,
     pub code_challenge: Option<String>,
     pub code_challenge_method: Option<String>,
     pub nonce: Option<String>,
+    pub prompt: Option<String>,
+    /// Maximum seconds since the user last authenticated before they must
+    /// sign in again (OIDC Core §3.1.2.1).
+    #[serde(
+        default,
+        deserialize_with = "deserialize_max_age",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_age: Option<u64>,
+    /// Signed request object carrying the parameters (RFC 9101).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request: Option<String>,
+    /// Reference to a request pushed to `/par` (RFC 9126), or to a request
+    /// object at one of the client's registered `request_uris`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_uri: Option<String>,
+    /// API the access token is for (RFC 8707).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource: Option<String>,
+    /// How the authorization response is returned: `query`, `fragment`,
+    /// `form_post` or one of their signed JARM variants.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_mode: Option<String>,
+}
+
+/// `max_age` is a string in query strings and forms but a number in request
+/// objects.
+pub(super) fn deserialize_max_age<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Number(u64),
+        String(String),
+    }
+    let max_age = match Option::<Raw>::deserialize(deserializer)? {
+        None => return Ok(None),
+        Some(Raw::Number(n)) => n,
+        Some(Raw::String(s)) => s.parse().map_err(serde::de::Error::custom)?,
+    };
+    if i64::try_from(max_age).is_err() {
+        return Err(serde::de::Error::custom("max_age is out of range"));
+    }
+    Ok(Some(max_age))
 }
 
 #[derive(Template)]
@@ -33,14 +99,8 @@ struct LoginTemplate {
     realm_name: String,
     error_message: Option<String>,
     csrf_token: String,
-    client_id: String,
-    redirect_uri: String,
-    response_type: String,
-    scope: String,
-    state: String,
-    code_challenge: String,
-    code_challenge_method: String,
-    nonce: Option<String>,
+    /// Hidden fields that carry the authorization request.
+    fields: Vec<(&'static str, String)>,
 }
 
 #[derive(Template)]
@@ -49,32 +109,72 @@ struct ErrorTemplate {
     message: String,
 }
 
-fn validate_authorize_params(q: &AuthorizeQuery) -> Result<(), String> {
+/// Render the generic error page, for requests whose errors can't be sent
+/// back to a client.
+pub(super) fn render_error_page(message: &str) -> Result<Response, AppError> {
+    let tmpl = ErrorTemplate {
+        message: message.to_string(),
+    };
+    let html = tmpl
+        .render()
+        .map_err(|e: askama::Error| AppError::Internal(e.to_string()))?;
+    Ok((StatusCode::BAD_REQUEST, Html(html)).into_response())
+}
+
+pub(super) fn validate_authorize_params(q: &AuthorizeQuery) -> Result<(), AppError> {
+    let invalid = |msg: &str| Err(AppError::BadRequest("invalid_request", msg.to_string()));
     if q.response_type != "code" {
-        return Err("unsupported response_type".to_string());
+        return Err(AppError::BadRequest(
+            "unsupported_response_type",
+            "only the code response_type is supported".to_string(),
+        ));
     }
     if q.code_challenge.is_none() || q.code_challenge.as_deref() == Some("") {
-        return Err("code_challenge is required (PKCE)".to_string());
+        return invalid("code_challenge is required (PKCE)");
     }
     match q.code_challenge_method.as_deref() {
         Some("S256") => {}
-        None => return Err("code_challenge_method is required (must be S256)".to_string()),
-        _ => return Err("only S256 code_challenge_method is supported".to_string()),
+        None => return invalid("code_challenge_method is required (must be S256)"),
+        _ => return invalid("only S256 code_challenge_method is supported"),
+    }
+    validate_response_mode(q)
+}
+
+/// Downscope an OpenID request to the client's allowed scopes. `openid` must
+/// be both requested and allowed.
+pub(super) fn grant_openid_scope(requested: &str, client: &Client) -> Result<String, AppError> {
+    if !has_scope(requested, "openid") {
+        return Err(AppError::BadRequest(
+            "invalid_scope",
+            "scope must include openid".to_string(),
+        ));
     }
-    Ok(())
+    let granted = downscope(requested, &client.allowed_scopes);
+    if !has_scope(&granted, "openid") {
+        return Err(AppError::BadRequest(
+            "invalid_scope",
+            "client is not allowed the openid scope".to_string(),
+        ));
+    }
+    Ok(granted)
 }
 
 /// GET /realms/{realm}/authorize — show login form (or redirect if session exists)
 pub async fn authorize_get(
     State(state): State<AppState>,
     Path(realm): Path<String>,
-    Query(q): Query<AuthorizeQuery>,
+    Query(mut q): Query<AuthorizeQuery>,
     headers: HeaderMap,
 ) -> Result<Response, AppError> {
-    // Validate params
-    if let Err(msg) = validate_authorize_params(&q) {
-        let tmpl = ErrorTemplate { message: msg };
-        return Ok(Html(tmpl.render().unwrap_or_default()).into_response());
+    // Fetch a request object hosted by the client before taking the lock
+    if let Some(uri) = q
+        .request_uri
+        .take_if(|uri| !uri.starts_with(PAR_REQUEST_URI_PREFIX))
+    {
+        match fetch_request_object(&state, &realm, &q.client_id, &uri).await? {
+            Ok(request) => q.request = Some(request),
+            Err(msg) => return render_error_page(&msg),
+        }
     }
 
     let conn = state
@@ -84,76 +184,144 @@ pub async fn authorize_get(
     let realm_obj = db::realm::get_realm_by_name(&conn, &realm)?
         .ok_or_else(|| AppError::NotFound(format!("realm '{realm}' not found")))?;
 
-    // Validate client
-    let client = db::client::get_client_by_client_id(&conn, &realm_obj.id, &q.client_id)?
-        .ok_or_else(|| AppError::BadRequest("unknown client_id".to_string()))?;
+    // A pushed request replaces the inline parameters entirely
+    let pushed = q.request_uri.is_some();
+    if let Some(request_uri) = q.request_uri.take() {
+        match load_pushed_request(&conn, &realm_obj.id, &q.client_id, &request_uri)? {
+            Ok(stored) => q = stored,
+            Err(msg) => return render_error_page(&msg),
+        }
+    }
 
+    // So does a signed request object
+    if let Some(request) = q.request.take() {
+        let issuer = format!("{}/realms/{}", state.config.issuer_base_url, realm);
+        match load_request_object(&conn, &realm_obj.id, &issuer, &q.client_id, &request)? {
+            Ok(signed) => q = signed,
+            Err(msg) => return render_error_page(&msg),
+        }
+    }
+
+    // Until the redirect URI is known to be the client's, errors are shown
+    // to the user rather than sent anywhere (RFC 6749 §4.1.2.1)
+    let Some(client) = db::client::get_client_by_client_id(&conn, &realm_obj.id, &q.client_id)?
+    else {
+        return render_error_page("Unknown client_id");
+    };
     if !client.redirect_uris.contains(&q.redirect_uri) {
-        return Err(AppError::BadRequest(
-            "redirect_uri not registered".to_string(),
-        ));
+        return render_error_page("redirect_uri is not registered for this client");
+    }
+
+    // From here on errors go back to the client
+    let fail = |q: &AuthorizeQuery, err: AppError| {
+        redirect_error(&conn, &state, &realm, &realm_obj.id, q, err)
+    };
+    if let Err(e) = validate_authorize_params(&q) {
+        return fail(&q, e);
+    }
+    if client.require_par && !pushed {
+        return fail(
+            &q,
+            AppError::BadRequest(
+                "invalid_request",
+                "client requires pushed authorization requests".to_string(),
+            ),
+        );
+    }
+    match grant_openid_scope(q.scope.as_deref().unwrap_or_default(), &client) {
+        Ok(scope) => q.scope = Some(scope),
+        Err(e) => return fail(&q, e),
+    }
+    if let Err(e) = find_resource(&conn, &realm_obj.id, q.resource.as_deref()) {
+        return fail(&q, e);
     }
 
-    // Check for existing session
-    let session_cookie_name = format!("anz_session_{realm}");
-    if let Some(cookie_header) = headers.get(axum::http::header::COOKIE) {
-        if let Ok(cookies) = cookie_header.to_str() {
-            if let Some(session_token) = extract_cookie(cookies, &session_cookie_name) {
-                let token_hash = hex::encode(Sha256::digest(session_token.as_bytes()).as_slice());
-                if let Ok(Some(session)) =
-                    db::session::get_session_by_token_hash(&conn, &realm_obj.id, &token_hash)
-                {
-                    // Session exists — generate auth code and redirect
-                    return generate_auth_code_redirect(
-                        &conn,
-                        &state,
-                        &realm_obj.id,
+    // prompt=none can't be combined with other values (OIDC Core §3.1.2.1)
+    let prompt = q.prompt.as_deref().unwrap_or_default();
+    let silent = has_scope(prompt, "none");
+    if silent && prompt.split_whitespace().count() > 1 {
+        return fail(
+            &q,
+            AppError::BadRequest(
+                "invalid_request",
+                "prompt=none cannot be combined with other values".to_string(),
+            ),
+        );
+    }
+
+    // Reuse an existing session unless the client wants a fresh login or the
+    // user authenticated longer ago than max_age allows; a max_age beyond
+    // what a Duration can hold sets no limit
+    let session = session_from_cookie(&conn, &realm, &realm_obj.id, &headers).filter(|s| {
+        !has_scope(prompt, "login")
+            && q.max_age
+                .and_then(|max_age| Duration::try_seconds(max_age as i64))
+                .is_none_or(|max_age| Utc::now() - s.auth_time <= max_age)
+    });
+    match session {
+        Some(session) => {
+            if consent_required(
+                &conn,
+                &client,
+                &session.user_id,
+                q.scope.as_deref().unwrap_or_default(),
+                q.prompt.as_deref(),
+            )? {
+                if silent {
+                    return fail(
                         &q,
-                        &session.user_id,
+                        AppError::BadRequest(
+                            "consent_required",
+                            "the user has not consented to the requested scopes".to_string(),
+                        ),
                     );
                 }
+                let fields = request_fields(&conn, &realm_obj.id, &client, &q)?;
+                return render_consent(&realm, &client, &q, fields, None);
             }
+            // Session exists — generate auth code and redirect
+            return generate_auth_code_redirect(&conn, &state, &realm, &realm_obj.id, &q, &session);
+        }
+        None if silent => {
+            return fail(
+                &q,
+                AppError::BadRequest("login_required", "the user is not signed in".to_string()),
+            )
         }
+        None => {}
     }
 
     // No session — show login form
-    let csrf_token = csrf::generate_csrf_token();
-    let csrf_cookie =
-        format!("anz_csrf_{realm}={csrf_token}; HttpOnly; SameSite=Lax; Path=/realms/{realm}");
-
-    let tmpl = LoginTemplate {
-        realm_name: realm,
-        error_message: None,
-        csrf_token,
-        client_id: q.client_id,
-        redirect_uri: q.redirect_uri,
-        response_type: q.response_type,
-        scope: q.scope.unwrap_or_else(|| "openid".to_string()),
-        state: q.state.unwrap_or_default(),
-        code_challenge: q.code_challenge.unwrap_or_default(),
-        code_challenge_method: q.code_challenge_method.unwrap_or_default(),
-        nonce: q.nonce,
-    };
-
-    let html = tmpl
-        .render()
-        .map_err(|e: askama::Error| AppError::Internal(e.to_string()))?;
-    Ok(([(SET_COOKIE, csrf_cookie)], Html(html)).into_response())
+    let fields = request_fields(&conn, &realm_obj.id, &client, &q)?;
+    render_login(&realm, fields, None)
 }
 
+/// Login form fields. The request parameters are defaulted because a client
+/// that must push its requests sends only `client_id` and `request_uri`.
 #[derive(Debug, Deserialize)]
 pub struct AuthorizeForm {
     pub csrf_token: String,
     pub username: String,
     pub password: String,
     pub client_id: String,
+    #[serde(default)]
     pub redirect_uri: String,
+    #[serde(default)]
     pub response_type: String,
+    #[serde(default)]
     pub scope: String,
+    #[serde(default)]
     pub state: String,
+    #[serde(default)]
     pub code_challenge: String,
+    #[serde(default)]
     pub code_challenge_method: String,
     pub nonce: Option<String>,
+    pub prompt: Option<String>,
+    pub resource: Option<String>,
+    pub response_mode: Option<String>,
+    /// Stored request the form refers to, for clients that must push theirs.
+    pub request_uri: Option<String>,
 }
 
 /// POST /realms/{realm}/authorize — validate credentials, issue auth code, redirect
@@ -170,107 +338,334 @@ pub async fn authorize_post(
     let realm_obj = db::realm::get_realm_by_name(&conn, &realm)?
         .ok_or_else(|| AppError::NotFound(format!("realm '{realm}' not found")))?;
 
-    // Verify CSRF (double-submit cookie pattern)
-    let csrf_cookie_name = format!("anz_csrf_{realm}");
-    let csrf_from_cookie = headers
-        .get(axum::http::header::COOKIE)
-        .and_then(|v| v.to_str().ok())
-        .and_then(|cookies| extract_cookie(cookies, &csrf_cookie_name))
-        .unwrap_or_default();
-
-    if !csrf::verify_csrf_token(&form.csrf_token, &csrf_from_cookie) {
-        return render_login_error(&realm, &form, "Invalid request. Please try again.");
+    // Validate client and redirect_uri
+    let Some(client) = db::client::get_client_by_client_id(&conn, &realm_obj.id, &form.client_id)?
+    else {
+        return render_error_page("Unknown client_id");
+    };
+    let mut q = match submitted_request(
+        &conn,
+        &realm_obj.id,
+        &client,
+        form.request_uri.as_deref(),
+        form_query(&form),
+    )? {
+        Ok(q) => q,
+        Err(msg) => return render_error_page(&msg),
+    };
+    if !client.redirect_uris.contains(&q.redirect_uri) {
+        return render_error_page("redirect_uri is not registered for this client");
     }
 
-    // Validate client and redirect_uri
-    let client = db::client::get_client_by_client_id(&conn, &realm_obj.id, &form.client_id)?
-        .ok_or_else(|| AppError::BadRequest("unknown client_id".to_string()))?;
+    if !verify_csrf(&headers, &realm, &form.csrf_token) {
+        let fields = request_fields(&conn, &realm_obj.id, &client, &q)?;
+        return render_login(&realm, fields, Some("Invalid request. Please try again."));
+    }
 
-    if !client.redirect_uris.contains(&form.redirect_uri) {
-        return Err(AppError::BadRequest(
-            "redirect_uri not registered".to_string(),
-        ));
+    // Inline parameters round-trip through the form, so check them again
+    if let Err(e) = validate_response_mode(&q) {
+        return redirect_error(&conn, &state, &realm, &realm_obj.id, &q, e);
+    }
+    match grant_openid_scope(q.scope.as_deref().unwrap_or_default(), &client) {
+        Ok(scope) => q.scope = Some(scope),
+        Err(e) => return redirect_error(&conn, &state, &realm, &realm_obj.id, &q, e),
     }
 
     // Authenticate user
-    let user = db::user::get_user_by_username(&conn, &realm_obj.id, &form.username)?;
-    let authenticated = match &user {
-        Some(u) => pw::verify_password(&form.password, &u.password_hash),
-        None => {
-            // Timing oracle prevention
-            pw::dummy_verify();
-            false
-        }
+    let Some(user) = authenticate_user(&conn, &realm_obj.id, &form.username, &form.password)?
+    else {
+        let fields = request_fields(&conn, &realm_obj.id, &client, &q)?;
+        return render_login(&realm, fields, Some("Invalid username or password"));
     };
 
-    if !authenticated {
-        return render_login_error(&realm, &form, "Invalid username or password");
-    }
-
-    let user = user.unwrap();
+    // Signing in again as the session's user refreshes its auth_time;
+    // signing in as someone else ends their session
+    let (session, session_cookie) =
+        match session_from_cookie(&conn, &realm, &realm_obj.id, &headers) {
+            Some(existing) if existing.user_id == user.id => {
+                (db::session::reauthenticate_session(&conn, &existing)?, None)
+            }
+            existing => {
+                if let Some(existing) = existing {
+                    db::session::end_session(&conn, &existing)?;
+                }
+                let (session, cookie) =
+                    create_session(&conn, &state, &realm, &realm_obj.id, &user.id)?;
+                (session, Some(cookie))
+            }
+        };
 
-    // Create session
-    let session_token = generate_random_token();
-    let session_token_hash = hex::encode(Sha256::digest(session_token.as_bytes()).as_slice());
-    let session_lifetime = Duration::seconds(state.config.session_lifetime_secs as i64);
-    let session_expires = Utc::now() + session_lifetime;
-    db::session::create_session(
+    if consent_required(
         &conn,
-        &realm_obj.id,
+        &client,
         &user.id,
-        &session_token_hash,
-        session_expires,
-    )?;
+        q.scope.as_deref().unwrap_or_default(),
+        q.prompt.as_deref(),
+    )? {
+        let fields = request_fields(&conn, &realm_obj.id, &client, &q)?;
+        return render_consent(&realm, &client, &q, fields, session_cookie);
+    }
 
-    let session_cookie = format!(
-        "anz_session_{realm}={session_token}; HttpOnly; SameSite=Lax; Path=/realms/{realm}; Max-Age={}",
-        state.config.session_lifetime_secs
-    );
+    let response = generate_auth_code_redirect(&conn, &state, &realm, &realm_obj.id, &q, &session)?;
 
-    // Generate auth code and redirect
-    let q = AuthorizeQuery {
-        response_type: form.response_type,
-        client_id: form.client_id,
-        redirect_uri: form.redirect_uri,
-        scope: Some(form.scope),
-        state: Some(form.state),
-        code_challenge: Some(form.code_challenge),
-        code_challenge_method: Some(form.code_challenge_method),
-        nonce: form.nonce,
-    };
+    // Clear CSRF cookie, set session cookie. AppendHeaders, because an array
+    // of header pairs would overwrite one Set-Cookie with the other.
+    let mut cookies = vec![(SET_COOKIE, clear_csrf_cookie(&realm))];
+    if let Some(session_cookie) = session_cookie {
+        cookies.push((SET_COOKIE, session_cookie));
+    }
+    Ok((AppendHeaders(cookies), response).into_response())
+}
 
-    let (redirect_response, _) =
-        generate_auth_code_redirect_inner(&conn, &state, &realm_obj.id, &q, &user.id)?;
+/// Hidden fields that carry `q` through the login and consent screens. A
+/// client that must push its requests gets only a reference to the request,
+/// stored again here, so the browser can't swap in parameters of its own.
+pub(super) fn request_fields(
+    conn: &rusqlite::Connection,
+    realm_id: &str,
+    client: &Client,
+    q: &AuthorizeQuery,
+) -> Result<Vec<(&'static str, String)>, AppError> {
+    if client.require_par {
+        let params = serde_json::to_string(q).map_err(|e| AppError::Internal(e.to_string()))?;
+        let request_uri = format!("{PAR_REQUEST_URI_PREFIX}{}", generate_random_token());
+        let request_uri_hash = hex::encode(Sha256::digest(request_uri.as_bytes()).as_slice());
+        db::pushed_request::insert_pushed_request(
+            conn,
+            realm_id,
+            &client.client_id,
+            &request_uri_hash,
+            &params,
+            Utc::now() + Duration::seconds(SIGN_IN_LIFETIME_SECS),
+        )?;
+        return Ok(vec![
+            ("client_id", client.client_id.clone()),
+            ("request_uri", request_uri),
+        ]);
+    }
 
-    // Clear CSRF cookie, set session cookie
-    let clear_csrf =
-        format!("anz_csrf_{realm}=; HttpOnly; SameSite=Lax; Path=/realms/{realm}; Max-Age=0");
+    let mut fields = vec![
+        ("client_id", q.client_id.clone()),
+        ("redirect_uri", q.redirect_uri.clone()),
+        ("response_type", q.response_type.clone()),
+        ("scope", q.scope.clone().unwrap_or_default()),
+        ("state", q.state.clone().unwrap_or_default()),
+        (
+            "code_challenge",
+            q.code_challenge.clone().unwrap_or_default(),
+        ),
+        (
+            "code_challenge_method",
+            q.code_challenge_method.clone().unwrap_or_default(),
+        ),
+    ];
+    let optional = [
+        ("nonce", &q.nonce),
+        ("prompt", &q.prompt),
+        ("resource", &q.resource),
+        ("response_mode", &q.response_mode),
+    ];
+    for (name, value) in optional {
+        if let Some(value) = value {
+            fields.push((name, value.clone()));
+        }
+    }
+    Ok(fields)
+}
 
-    Ok((
-        [(SET_COOKIE, session_cookie), (SET_COOKIE, clear_csrf)],
-        redirect_response,
-    )
-        .into_response())
+/// The authorization request a login or consent form submitted: the stored
+/// request for clients that must push theirs, otherwise the inline fields.
+pub(super) fn submitted_request(
+    conn: &rusqlite::Connection,
+    realm_id: &str,
+    client: &Client,
+    request_uri: Option<&str>,
+    inline: AuthorizeQuery,
+) -> Result<Result<AuthorizeQuery, String>, AppError> {
+    if !client.require_par {
+        return Ok(Ok(inline));
+    }
+    let Some(request_uri) = request_uri else {
+        return Ok(Err(
+            "client requires pushed authorization requests".to_string()
+        ));
+    };
+    load_pushed_request(conn, realm_id, &client.client_id, request_uri)
 }
 
-fn generate_auth_code_redirect(
+/// The authorization request carried inline through the login form.
+fn form_query(form: &AuthorizeForm) -> AuthorizeQuery {
+    AuthorizeQuery {
+        response_type: form.response_type.clone(),
+        client_id: form.client_id.clone(),
+        redirect_uri: form.redirect_uri.clone(),
+        scope: Some(form.scope.clone()),
+        state: Some(form.state.clone()),
+        code_challenge: Some(form.code_challenge.clone()),
+        code_challenge_method: Some(form.code_challenge_method.clone()),
+        nonce: form.nonce.clone(),
+        prompt: form.prompt.clone(),
+        max_age: None,
+        request: None,
+        request_uri: None,
+        resource: form.resource.clone(),
+        response_mode: form.response_mode.clone(),
+    }
+}
+
+/// Send an OAuth error to the client's redirect URI with the request's
+/// `state` (RFC 6749 §4.1.2.1); any other error is returned as is. Only for
+/// requests whose redirect URI has been checked against the client.
+pub(super) fn redirect_error(
     conn: &rusqlite::Connection,
     state: &AppState,
+    realm: &str,
     realm_id: &str,
     q: &AuthorizeQuery,
-    user_id: &str,
+    err: AppError,
 ) -> Result<Response, AppError> {
-    let (redirect, _) = generate_auth_code_redirect_inner(conn, state, realm_id, q, user_id)?;
-    Ok(redirect.into_response())
+    let AppError::BadRequest(error, description) = err else {
+        return Err(err);
+    };
+    let mut params = vec![("error", error), ("error_description", &description)];
+    if let Some(state) = &q.state {
+        params.push(("state", state));
+    }
+    authorization_response(conn, state, realm, realm_id, q, &params)
 }
 
-fn generate_auth_code_redirect_inner(
+/// Verify a signed request object against the client's registered keys and
+/// return the parameters it carries. Only the object's parameters are used;
+/// the query must merely name the same client (RFC 9101 §6.3).
+pub(super) fn load_request_object(
     conn: &rusqlite::Connection,
+    realm_id: &str,
+    issuer: &str,
+    client_id: &str,
+    request: &str,
+) -> Result<Result<AuthorizeQuery, String>, AppError> {
+    let Some(client) = db::client::get_client_by_client_id(conn, realm_id, client_id)? else {
+        return Ok(Err("unknown client_id".to_string()));
+    };
+    let Some(jwks) = client_jwks(&client)? else {
+        return Ok(Err(
+            "client has no keys registered for request objects".to_string()
+        ));
+    };
+    let claims = match jwt::decode_request_object(request, &jwks, client_id, issuer) {
+        Ok(claims) => claims,
+        Err(e) => {
+            tracing::warn!("Request object for '{client_id}' rejected: {e}");
+            return Ok(Err("invalid request object".to_string()));
+        }
+    };
+    let q: AuthorizeQuery = match serde_json::from_value(claims) {
+        Ok(q) => q,
+        Err(e) => return Ok(Err(format!("invalid request object: {e}"))),
+    };
+    if q.client_id != client_id {
+        return Ok(Err("request object client_id does not match".to_string()));
+    }
+    if q.request.is_some() || q.request_uri.is_some() {
+        return Ok(Err(
+            "request object must not nest request or request_uri".to_string()
+        ));
+    }
+    Ok(Ok(q))
+}
+
+/// Fetch a request object from one of the client's registered `request_uris`.
+async fn fetch_request_object(
     state: &AppState,
+    realm: &str,
+    client_id: &str,
+    uri: &str,
+) -> Result<Result<String, String>, AppError> {
+    let registered = {
+        let conn = state
+            .db
+            .lock()
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        let realm_obj = db::realm::get_realm_by_name(&conn, realm)?
+            .ok_or_else(|| AppError::NotFound(format!("realm '{realm}' not found")))?;
+        db::client::get_client_by_client_id(&conn, &realm_obj.id, client_id)?
+            .is_some_and(|c| c.request_uris.iter().any(|r| r == uri))
+    };
+    if !registered {
+        return Ok(Err("request_uri not registered".to_string()));
+    }
+
+    let http = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(5))
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let body = match http.get(uri).send().await {
+        Ok(resp) if resp.status().is_success() => read_capped(resp, MAX_REQUEST_OBJECT_BYTES).await,
+        Ok(resp) => {
+            tracing::warn!("Fetching request object from {uri}: {}", resp.status());
+            None
+        }
+        Err(e) => {
+            tracing::warn!("Fetching request object from {uri}: {e}");
+            None
+        }
+    };
+    let request = body.and_then(|b| String::from_utf8(b).ok());
+    Ok(request
+        .map(|r| r.trim().to_string())
+        .ok_or_else(|| "could not fetch request object from request_uri".to_string()))
+}
+
+/// Read a response body of at most `limit` bytes, giving up as soon as it
+/// is known to be larger rather than buffering all of it.
+async fn read_capped(mut resp: reqwest::Response, limit: usize) -> Option<Vec<u8>> {
+    if resp.content_length().is_some_and(|len| len > limit as u64) {
+        tracing::warn!("Request object at {} is too large", resp.url());
+        return None;
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = resp.chunk().await.ok()? {
+        if body.len() + chunk.len() > limit {
+            tracing::warn!("Request object at {} is too large", resp.url());
+            return None;
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Some(body)
+}
+
+/// Consume the pushed request behind `request_uri`. It must have been pushed
+/// by the client named in the query.
+fn load_pushed_request(
+    conn: &rusqlite::Connection,
+    realm_id: &str,
+    client_id: &str,
+    request_uri: &str,
+) -> Result<Result<AuthorizeQuery, String>, AppError> {
+    let request_uri_hash = hex::encode(Sha256::digest(request_uri.as_bytes()).as_slice());
+    let Some(pushed) = db::pushed_request::get_pushed_request(conn, realm_id, &request_uri_hash)?
+    else {
+        return Ok(Err("invalid or expired request_uri".to_string()));
+    };
+    // Only the client it was pushed by may use, and so use up, a request_uri
+    if pushed.client_id != client_id {
+        return Ok(Err("request_uri was issued to another client".to_string()));
+    }
+    db::pushed_request::consume_pushed_request(conn, &pushed)?;
+    let q: AuthorizeQuery = serde_json::from_str(&pushed.params)
+        .map_err(|e| AppError::Internal(format!("corrupt pushed request: {e}")))?;
+    Ok(Ok(q))
+}
+
+pub(super) fn generate_auth_code_redirect(
+    conn: &rusqlite::Connection,
+    state: &AppState,
+    realm: &str,
     realm_id: &str,
     q: &AuthorizeQuery,
-    user_id: &str,
-) -> Result<(Redirect, String), AppError> {
+    session: &Session,
+) -> Result<Response, AppError> {
     let raw_code = generate_random_token();
     let code_hash = hex::encode(Sha256::digest(raw_code.as_bytes()).as_slice());
 
@@ -282,48 +677,45 @@ fn generate_auth_code_redirect_inner(
         &db::auth_code::NewAuthCode {
             realm_id,
             client_id: &q.client_id,
-            user_id,
+            user_id: &session.user_id,
             code_hash: &code_hash,
             redirect_uri: &q.redirect_uri,
             scopes: q.scope.as_deref().unwrap_or("openid"),
             code_challenge: q.code_challenge.as_deref().unwrap_or(""),
+            sid: &session.id,
+            nonce: q.nonce.as_deref().filter(|n| !n.is_empty()),
+            auth_time: session.auth_time,
+            resource: q.resource.as_deref(),
             expires_at,
         },
     )?;
 
     let state_param = q.state.as_deref().unwrap_or("");
-    let mut redirect_parsed = url::Url::parse(&q.redirect_uri)
-        .map_err(|e| AppError::Internal(format!("invalid redirect_uri: {e}")))?;
-    redirect_parsed
-        .query_pairs_mut()
-        .append_pair("code", &raw_code)
-        .append_pair("state", state_param);
-    let redirect_url = redirect_parsed.to_string();
-
-    Ok((Redirect::to(&redirect_url), raw_code))
+    authorization_response(
+        conn,
+        state,
+        realm,
+        realm_id,
+        q,
+        &[("code", &raw_code), ("state", state_param)],
+    )
 }
 
-fn render_login_error(
+/// Render the login form, carrying the request in `fields` (see
+/// [`request_fields`]).
+fn render_login(
     realm: &str,
-    form: &AuthorizeForm,
-    error_msg: &str,
+    fields: Vec<(&'static str, String)>,
+    error_msg: Option<&str>,
 ) -> Result<Response, AppError> {
     let csrf_token = csrf::generate_csrf_token();
-    let csrf_cookie =
-        format!("anz_csrf_{realm}={csrf_token}; HttpOnly; SameSite=Lax; Path=/realms/{realm}");
+    let csrf_cookie = csrf_cookie(realm, &csrf_token);
 
     let tmpl = LoginTemplate {
         realm_name: realm.to_string(),
-        error_message: Some(error_msg.to_string()),
+        error_message: error_msg.map(str::to_string),
         csrf_token,
-        client_id: form.client_id.clone(),
-        redirect_uri: form.redirect_uri.clone(),
-        response_type: form.response_type.clone(),
-        scope: form.scope.clone(),
-        state: form.state.clone(),
-        code_challenge: form.code_challenge.clone(),
-        code_challenge_method: form.code_challenge_method.clone(),
-        nonce: form.nonce.clone(),
+        fields,
     };
 
     let html = tmpl
@@ -332,7 +724,100 @@ fn render_login_error(
     Ok(([(SET_COOKIE, csrf_cookie)], Html(html)).into_response())
 }
 
-fn generate_random_token() -> String {
+/// Resolve the signed-in user from the realm's session cookie, if any.
+pub(super) fn session_from_cookie(
+    conn: &rusqlite::Connection,
+    realm: &str,
+    realm_id: &str,
+    headers: &HeaderMap,
+) -> Option<Session> {
+    let token_hash = session_token_hash(headers, realm)?;
+    db::session::get_session_by_token_hash(conn, realm_id, &token_hash)
+        .ok()
+        .flatten()
+}
+
+/// SHA-256 hash of the realm's session cookie, as stored in `sessions`.
+fn session_token_hash(headers: &HeaderMap, realm: &str) -> Option<String> {
+    let cookies = headers
+        .get(axum::http::header::COOKIE)
+        .and_then(|v| v.to_str().ok())?;
+    let session_token = extract_cookie(cookies, &format!("anz_session_{realm}"))?;
+    Some(hex::encode(
+        Sha256::digest(session_token.as_bytes()).as_slice(),
+    ))
+}
+
+/// Create a session for `user_id` and return it with its `Set-Cookie` value.
+pub(super) fn create_session(
+    conn: &rusqlite::Connection,
+    state: &AppState,
+    realm: &str,
+    realm_id: &str,
+    user_id: &str,
+) -> Result<(Session, String), AppError> {
+    let session_token = generate_random_token();
+    let session_token_hash = hex::encode(Sha256::digest(session_token.as_bytes()).as_slice());
+    let session_lifetime = Duration::seconds(state.config.session_lifetime_secs as i64);
+    let session_expires = Utc::now() + session_lifetime;
+    let session = db::session::create_session(
+        conn,
+        realm_id,
+        user_id,
+        &session_token_hash,
+        session_expires,
+    )?;
+
+    let cookie = format!(
+        "anz_session_{realm}={session_token}; HttpOnly; SameSite=Lax; Path=/realms/{realm}; Max-Age={}",
+        state.config.session_lifetime_secs
+    );
+    Ok((session, cookie))
+}
+
+/// Check a username and password against the realm's users.
+pub(super) fn authenticate_user(
+    conn: &rusqlite::Connection,
+    realm_id: &str,
+    username: &str,
+    password: &str,
+) -> Result<Option<User>, AppError> {
+    let user = db::user::get_user_by_username(conn, realm_id, username)?;
+    let authenticated = match &user {
+        Some(u) => pw::verify_password(password, &u.password_hash),
+        None => {
+            // Timing oracle prevention
+            pw::dummy_verify();
+            false
+        }
+    };
+    Ok(user.filter(|_| authenticated))
+}
+
+/// Verify a form's CSRF token against the realm's CSRF cookie (double-submit
+/// cookie pattern).
+pub(super) fn verify_csrf(headers: &HeaderMap, realm: &str, form_token: &str) -> bool {
+    let csrf_from_cookie = headers
+        .get(axum::http::header::COOKIE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|cookies| extract_cookie(cookies, &format!("anz_csrf_{realm}")))
+        .unwrap_or_default();
+    csrf::verify_csrf_token(form_token, &csrf_from_cookie)
+}
+
+pub(super) fn clear_session_cookie(realm: &str) -> String {
+    format!("anz_session_{realm}=; HttpOnly; SameSite=Lax; Path=/realms/{realm}; Max-Age=0")
+}
+
+pub(super) fn csrf_cookie(realm: &str, csrf_token: &str) -> String {
+    format!("anz_csrf_{realm}={csrf_token}; HttpOnly; SameSite=Lax; Path=/realms/{realm}")
+}
+
+pub(super) fn clear_csrf_cookie(realm: &str) -> String {
+    format!("anz_csrf_{realm}=; HttpOnly; SameSite=Lax; Path=/realms/{realm}; Max-Age=0")
+}
+
+pub(super) fn generate_random_token() -> String {
     let mut bytes = [0u8; 32];
     rand::thread_rng().fill_bytes(&mut bytes);
     URL_SAFE_NO_PAD.encode(bytes)
@@ -354,3 +839,46 @@ mod hex {
         bytes.iter().map(|b| format!("{b:02x}")).collect()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize)]
+    struct MaxAge {
+        #[serde(default, deserialize_with = "deserialize_max_age")]
+        max_age: Option<u64>,
+    }
+
+    fn max_age(value: serde_json::Value) -> Result<Option<u64>, serde_json::Error> {
+        serde_json::from_value::<MaxAge>(value).map(|q| q.max_age)
+    }
+
+    #[test]
+    fn max_age_accepts_strings_and_numbers() {
+        assert_eq!(max_age(json!({})).unwrap(), None);
+        assert_eq!(max_age(json!({ "max_age": null })).unwrap(), None);
+        assert_eq!(max_age(json!({ "max_age": 0 })).unwrap(), Some(0));
+        assert_eq!(max_age(json!({ "max_age": 300 })).unwrap(), Some(300));
+        assert_eq!(max_age(json!({ "max_age": "300" })).unwrap(), Some(300));
+    }
+
+    #[test]
+    fn max_age_rejects_negative_and_malformed_values() {
+        assert!(max_age(json!({ "max_age": -1 })).is_err());
+        assert!(max_age(json!({ "max_age": "-1" })).is_err());
+        assert!(max_age(json!({ "max_age": "" })).is_err());
+        assert!(max_age(json!({ "max_age": "5m" })).is_err());
+        assert!(max_age(json!({ "max_age": 1.5 })).is_err());
+    }
+
+    #[test]
+    fn max_age_rejects_values_beyond_i64() {
+        let max = i64::MAX as u64;
+        assert_eq!(max_age(json!({ "max_age": max })).unwrap(), Some(max));
+        assert!(max_age(json!({ "max_age": max + 1 })).is_err());
+        assert!(max_age(json!({ "max_age": u64::MAX.to_string() })).is_err());
+        assert!(max_age(json!({ "max_age": "99999999999999999999999" })).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use std::time::Duration as StdDuration;
+
+use chrono::{Duration, Utc};
+
+use super::subject::subject_for;
+use super::AppState;
+use crate::crypto::{keys, token as jwt};
+use crate::db;
+use crate::models::BackchannelLogout;
+
+/// How often expired sessions are swept and the delivery queue is drained.
+const TICK_SECS: u64 = 10;
+/// Notifications handled per tick.
+const BATCH_SIZE: i64 = 50;
+/// Give up on a notification after this many failed deliveries.
+const MAX_ATTEMPTS: i64 = 8;
+/// First retry delay; doubled after each failure.
+const RETRY_BASE_SECS: i64 = 30;
+const LOGOUT_TOKEN_LIFETIME_SECS: u64 = 120;
+
+/// Background task that ends expired sessions and delivers queued
+/// back-channel logout tokens (OIDC Back-Channel Logout 1.0), retrying
+/// failed deliveries with exponential backoff.
+pub async fn run(state: AppState) {
+    let http = match reqwest::Client::builder()
+        .timeout(StdDuration::from_secs(10))
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+    {
+        Ok(c) => c,
+        Err(e) => {
+            tracing::error!("Back-channel logout disabled: {e}");
+            return;
+        }
+    };
+
+    let mut ticker = tokio::time::interval(StdDuration::from_secs(TICK_SECS));
+    loop {
+        ticker.tick().await;
+
+        let deliveries = match prepare_deliveries(&state) {
+            Ok(d) => d,
+            Err(e) => {
+                tracing::error!("Back-channel logout: {e:#}");
+                continue;
+            }
+        };
+
+        for (logout, token) in deliveries {
+            let result = http
+                .post(&logout.backchannel_logout_uri)
+                .form(&[("logout_token", token.as_str())])
+                .send()
+                .await;
+            let error = match result {
+                Ok(resp) if resp.status().is_success() => None,
+                Ok(resp) => Some(format!("HTTP {}", resp.status())),
+                Err(e) => Some(e.to_string()),
+            };
+            if let Err(e) = record_delivery(&state, &logout, error) {
+                tracing::error!("Back-channel logout: {e:#}");
+            }
+        }
+    }
+}
+
+/// End expired sessions, then sign a logout token for each due notification.
+fn prepare_deliveries(state: &AppState) -> anyhow::Result<Vec<(BackchannelLogout, String)>> {
+    let conn = state
+        .db
+        .lock()
+        .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+
+    for session in db::session::list_expired_sessions(&conn)? {
+        db::session::end_session(&conn, &session)?;
+    }
+
+    let mut deliveries = Vec::new();
+    for logout in db::backchannel_logout::list_due_backchannel_logouts(&conn, BATCH_SIZE)? {
+        let signing_key = db::signing_key::get_active_signing_key(&conn, &logout.realm_id)?
+            .ok_or_else(|| anyhow::anyhow!("no signing key for realm '{}'", logout.realm_name))?;
+        let encoding_key = keys::encoding_key_from_pem(&signing_key.private_key_pem)?;
+
+        let issuer = format!(
+            "{}/realms/{}",
+            state.config.issuer_base_url, logout.realm_name
+        );
+        // The client knows the user by the same sub its ID tokens carried
+        let sub = match db::client::get_client_by_client_id(
+            &conn,
+            &logout.realm_id,
+            &logout.client_id,
+        )? {
+            Some(client) => subject_for(&conn, &client, &logout.user_id)?,
+            None => logout.user_id.clone(),
+        };
+        let claims = jwt::build_logout_token_claims(
+            &issuer,
+            &sub,
+            &logout.client_id,
+            &logout.sid,
+            LOGOUT_TOKEN_LIFETIME_SECS,
+        );
+        let token =
+            jwt::encode_jwt_with_typ(&claims, "logout+jwt", &signing_key.kid, &encoding_key)?;
+        deliveries.push((logout, token));
+    }
+    Ok(deliveries)
+}
+
+fn record_delivery(
+    state: &AppState,
+    logout: &BackchannelLogout,
+    error: Option<String>,
+) -> anyhow::Result<()> {
+    let conn = state
+        .db
+        .lock()
+        .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+
+    let Some(error) = error else {
+        tracing::info!(
+            "Delivered back-channel logout to '{}' in realm '{}'",
+            logout.client_id,
+            logout.realm_name
+        );
+        return db::backchannel_logout::delete_backchannel_logout(&conn, &logout.id);
+    };
+
+    let attempts = logout.attempts + 1;
+    if attempts >= MAX_ATTEMPTS {
+        tracing::warn!(
+            "Giving up on back-channel logout to '{}' after {attempts} attempts: {error}",
+            logout.client_id
+        );
+        return db::backchannel_logout::delete_backchannel_logout(&conn, &logout.id);
+    }
+
+    let delay = Duration::seconds(RETRY_BASE_SECS << (attempts - 1));
+    tracing::warn!(
+        "Back-channel logout to '{}' failed ({error}); retrying in {}s",
+        logout.client_id,
+        delay.num_seconds()
+    );
+    db::backchannel_logout::reschedule_backchannel_logout(
+        &conn,
+        &logout.id,
+        attempts,
+        Utc::now() + delay,
+    )
+}
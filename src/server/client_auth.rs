@@ -0,0 +1,167 @@
+use axum::http::HeaderMap;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{DateTime, Utc};
+use jsonwebtoken::jwk::JwkSet;
+use rusqlite::Connection;
+use serde::Deserialize;
+
+use super::error::AppError;
+use crate::crypto::{keys, password as pw, token as jwt};
+use crate::db;
+use crate::models::{Client, ClientType};
+
+const JWT_BEARER_ASSERTION: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
+/// Client authentication fields that may accompany a back-channel form post
+/// (`client_secret_post`, `private_key_jwt`, or a bare `client_id` for
+/// public clients).
+#[derive(Debug, Default, Deserialize)]
+pub struct ClientAuthForm {
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub client_assertion_type: Option<String>,
+    pub client_assertion: Option<String>,
+}
+
+/// Authenticate the caller of a back-channel endpoint as a registered client.
+///
+/// Credentials come from a signed client assertion (`private_key_jwt`,
+/// RFC 7523), HTTP Basic (`client_secret_basic`, RFC 6749 §2.3.1) or the form
+/// fields. Confidential clients must present a valid assertion or secret;
+/// public clients only identify themselves with `client_id`. `endpoint` is
+/// the URL being called, accepted as an assertion audience alongside the
+/// issuer and token endpoint.
+pub fn authenticate_client(
+    conn: &Connection,
+    realm_id: &str,
+    issuer: &str,
+    endpoint: &str,
+    headers: &HeaderMap,
+    form: &ClientAuthForm,
+) -> Result<Client, AppError> {
+    if let Some(assertion) = &form.client_assertion {
+        return authenticate_assertion(conn, realm_id, issuer, endpoint, form, assertion);
+    }
+
+    let (client_id, client_secret) = match basic_credentials(headers)? {
+        Some(creds) => creds,
+        None => {
+            let client_id = form.client_id.clone().ok_or_else(|| {
+                AppError::InvalidClient("client authentication required".to_string())
+            })?;
+            (client_id, form.client_secret.clone())
+        }
+    };
+
+    let client = match db::client::get_client_by_client_id(conn, realm_id, &client_id)? {
+        Some(c) => c,
+        None => {
+            // Timing oracle prevention
+            if client_secret.is_some() {
+                pw::dummy_verify();
+            }
+            return Err(AppError::InvalidClient("invalid client".to_string()));
+        }
+    };
+
+    if client.client_type == ClientType::Confidential {
+        let authenticated = match (&client_secret, &client.client_secret_hash) {
+            (Some(secret), Some(hash)) => pw::verify_password(secret, hash),
+            _ => false,
+        };
+        if !authenticated {
+            return Err(AppError::InvalidClient("invalid client".to_string()));
+        }
+    }
+
+    Ok(client)
+}
+
+fn authenticate_assertion(
+    conn: &Connection,
+    realm_id: &str,
+    issuer: &str,
+    endpoint: &str,
+    form: &ClientAuthForm,
+    assertion: &str,
+) -> Result<Client, AppError> {
+    if form.client_assertion_type.as_deref() != Some(JWT_BEARER_ASSERTION) {
+        return Err(AppError::BadRequest(
+            "invalid_request",
+            "unsupported client_assertion_type".to_string(),
+        ));
+    }
+
+    let client_id = jwt::peek_assertion_subject(assertion)
+        .ok_or_else(|| AppError::InvalidClient("invalid client".to_string()))?;
+    if form.client_id.as_deref().is_some_and(|id| id != client_id) {
+        return Err(AppError::InvalidClient("invalid client".to_string()));
+    }
+
+    let client = db::client::get_client_by_client_id(conn, realm_id, &client_id)?
+        .ok_or_else(|| AppError::InvalidClient("invalid client".to_string()))?;
+    let jwks = client_jwks(&client)?
+        .ok_or_else(|| AppError::InvalidClient("invalid client".to_string()))?;
+
+    let audiences = vec![
+        issuer.to_string(),
+        format!("{issuer}/token"),
+        endpoint.to_string(),
+    ];
+    let claims = jwt::decode_client_assertion(assertion, &jwks, &client.client_id, &audiences)
+        .map_err(|e| {
+            tracing::warn!("Client assertion for '{}' rejected: {e}", client.client_id);
+            AppError::InvalidClient("invalid client".to_string())
+        })?;
+
+    let expires_at = DateTime::<Utc>::from_timestamp(claims.exp, 0).unwrap_or_else(Utc::now);
+    if !db::jti::record_jti(conn, realm_id, &client.client_id, &claims.jti, expires_at)? {
+        tracing::warn!("Replayed client assertion for '{}'", client.client_id);
+        return Err(AppError::InvalidClient("invalid client".to_string()));
+    }
+
+    Ok(client)
+}
+
+/// Load the keys a client registered for `private_key_jwt` and signed
+/// request objects, if any.
+pub(super) fn client_jwks(client: &Client) -> Result<Option<JwkSet>, AppError> {
+    let json = match (&client.jwks, &client.jwks_path) {
+        (Some(jwks), _) => jwks.clone(),
+        (None, Some(path)) => std::fs::read_to_string(path)
+            .map_err(|e| AppError::Internal(format!("reading {path}: {e}")))?,
+        (None, None) => return Ok(None),
+    };
+    let jwks = keys::parse_jwks(&json)
+        .map_err(|e| AppError::Internal(format!("invalid JWKS for '{}': {e}", client.client_id)))?;
+    Ok(Some(jwks))
+}
+
+fn basic_credentials(headers: &HeaderMap) -> Result<Option<(String, Option<String>)>, AppError> {
+    let Some(encoded) = headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Basic "))
+    else {
+        return Ok(None);
+    };
+
+    let decoded = STANDARD
+        .decode(encoded.trim())
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .ok_or_else(|| AppError::InvalidClient("malformed Basic credentials".to_string()))?;
+    let (client_id, client_secret) = decoded
+        .split_once(':')
+        .ok_or_else(|| AppError::InvalidClient("malformed Basic credentials".to_string()))?;
+
+    let client_secret = Some(form_decode(client_secret)).filter(|s| !s.is_empty());
+    Ok(Some((form_decode(client_id), client_secret)))
+}
+
+/// Basic credentials are form-urlencoded before base64 (RFC 6749 §2.3.1).
+fn form_decode(value: &str) -> String {
+    percent_encoding::percent_decode_str(&value.replace('+', " "))
+        .decode_utf8_lossy()
+        .into_owned()
+}
@@ -0,0 +1,216 @@
+use askama::Template;
+use axum::extract::{Path, State};
+use axum::http::header::SET_COOKIE;
+use axum::http::HeaderMap;
+use axum::response::{AppendHeaders, Html, IntoResponse, Response};
+use axum::Form;
+use serde::Deserialize;
+
+use super::authorize::{
+    clear_csrf_cookie, csrf_cookie, generate_auth_code_redirect, grant_openid_scope,
+    redirect_error, render_error_page, session_from_cookie, submitted_request,
+    validate_authorize_params, verify_csrf, AuthorizeQuery,
+};
+use super::error::AppError;
+use super::scope::{describe, has_scope};
+use super::AppState;
+use crate::crypto::csrf;
+use crate::db;
+use crate::models::Client;
+
+#[derive(Template)]
+#[template(path = "consent.html")]
+struct ConsentTemplate {
+    realm_name: String,
+    client_name: String,
+    scopes: Vec<String>,
+    csrf_token: String,
+    /// Realm endpoint the decision is posted to.
+    action: &'static str,
+    /// Hidden fields that carry the request being consented to.
+    fields: Vec<(&'static str, String)>,
+}
+
+/// Consent form fields; like the login form, a client that must push its
+/// requests sends only `client_id` and `request_uri`.
+#[derive(Debug, Deserialize)]
+pub struct ConsentForm {
+    pub csrf_token: String,
+    pub action: String,
+    pub client_id: String,
+    #[serde(default)]
+    pub redirect_uri: String,
+    #[serde(default)]
+    pub response_type: String,
+    #[serde(default)]
+    pub scope: String,
+    #[serde(default)]
+    pub state: String,
+    #[serde(default)]
+    pub code_challenge: String,
+    #[serde(default)]
+    pub code_challenge_method: String,
+    pub nonce: Option<String>,
+    pub resource: Option<String>,
+    pub response_mode: Option<String>,
+    pub request_uri: Option<String>,
+}
+
+/// Whether the user must be asked before `client` receives `scope`.
+/// First-party clients never ask; third-party clients ask unless an earlier
+/// grant covers every requested scope, or the request says `prompt=consent`.
+pub(super) fn consent_required(
+    conn: &rusqlite::Connection,
+    client: &Client,
+    user_id: &str,
+    scope: &str,
+    prompt: Option<&str>,
+) -> Result<bool, AppError> {
+    if client.first_party {
+        return Ok(false);
+    }
+    if prompt.is_some_and(|p| has_scope(p, "consent")) {
+        return Ok(true);
+    }
+    let granted =
+        db::consent::get_consented_scopes(conn, &client.realm_id, user_id, &client.client_id)?
+            .unwrap_or_default();
+    Ok(!scope.split_whitespace().all(|s| has_scope(&granted, s)))
+}
+
+/// Render the consent screen for `q`, carried in `fields` (see
+/// [`request_fields`](super::authorize::request_fields)). `session_cookie` is
+/// set alongside the CSRF cookie when the user has just signed in.
+pub(super) fn render_consent(
+    realm: &str,
+    client: &Client,
+    q: &AuthorizeQuery,
+    fields: Vec<(&'static str, String)>,
+    session_cookie: Option<String>,
+) -> Result<Response, AppError> {
+    let scope = q.scope.as_deref().unwrap_or_default();
+    render_consent_page(realm, client, scope, "consent", fields, session_cookie)
+}
+
+/// Render the consent screen asking the user to grant `scope` to `client`.
+/// The decision is posted with `fields` to the realm's `action` endpoint.
+pub(super) fn render_consent_page(
+    realm: &str,
+    client: &Client,
+    scope: &str,
+    action: &'static str,
+    fields: Vec<(&'static str, String)>,
+    session_cookie: Option<String>,
+) -> Result<Response, AppError> {
+    let csrf_token = csrf::generate_csrf_token();
+    let tmpl = ConsentTemplate {
+        realm_name: realm.to_string(),
+        client_name: client
+            .display_name
+            .clone()
+            .unwrap_or_else(|| client.client_id.clone()),
+        scopes: scope.split_whitespace().map(describe).collect(),
+        csrf_token: csrf_token.clone(),
+        action,
+        fields,
+    };
+    let html = tmpl
+        .render()
+        .map_err(|e: askama::Error| AppError::Internal(e.to_string()))?;
+
+    let mut cookies = vec![(SET_COOKIE, csrf_cookie(realm, &csrf_token))];
+    if let Some(cookie) = session_cookie {
+        cookies.push((SET_COOKIE, cookie));
+    }
+    Ok((AppendHeaders(cookies), Html(html)).into_response())
+}
+
+/// POST /realms/{realm}/consent — record the user's decision and continue the authorization
+pub async fn consent_post(
+    State(state): State<AppState>,
+    Path(realm): Path<String>,
+    headers: HeaderMap,
+    Form(form): Form<ConsentForm>,
+) -> Result<Response, AppError> {
+    let conn = state
+        .db
+        .lock()
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let realm_obj = db::realm::get_realm_by_name(&conn, &realm)?
+        .ok_or_else(|| AppError::NotFound(format!("realm '{realm}' not found")))?;
+
+    if !verify_csrf(&headers, &realm, &form.csrf_token) {
+        return render_error_page("Invalid request. Please try again.");
+    }
+    let Some(session) = session_from_cookie(&conn, &realm, &realm_obj.id, &headers) else {
+        return render_error_page("Your session has expired. Please sign in again.");
+    };
+
+    let Some(client) = db::client::get_client_by_client_id(&conn, &realm_obj.id, &form.client_id)?
+    else {
+        return render_error_page("Unknown client_id");
+    };
+
+    let inline = AuthorizeQuery {
+        response_type: form.response_type,
+        client_id: form.client_id,
+        redirect_uri: form.redirect_uri,
+        scope: Some(form.scope),
+        state: Some(form.state),
+        code_challenge: Some(form.code_challenge),
+        code_challenge_method: Some(form.code_challenge_method),
+        nonce: form.nonce,
+        prompt: None,
+        max_age: None,
+        request: None,
+        request_uri: None,
+        resource: form.resource,
+        response_mode: form.response_mode,
+    };
+    let mut q = match submitted_request(
+        &conn,
+        &realm_obj.id,
+        &client,
+        form.request_uri.as_deref(),
+        inline,
+    )? {
+        Ok(q) => q,
+        Err(msg) => return render_error_page(&msg),
+    };
+    if !client.redirect_uris.contains(&q.redirect_uri) {
+        return render_error_page("redirect_uri is not registered for this client");
+    }
+
+    let checked = validate_authorize_params(&q)
+        .and_then(|()| grant_openid_scope(q.scope.as_deref().unwrap_or_default(), &client));
+    match checked {
+        Ok(scope) => q.scope = Some(scope),
+        Err(e) => return redirect_error(&conn, &state, &realm, &realm_obj.id, &q, e),
+    }
+
+    let clear_csrf = [(SET_COOKIE, clear_csrf_cookie(&realm))];
+
+    if form.action != "approve" {
+        // RFC 6749 §4.1.2.1: tell the client the user said no
+        let denied = redirect_error(
+            &conn,
+            &state,
+            &realm,
+            &realm_obj.id,
+            &q,
+            AppError::BadRequest("access_denied", "the user denied the request".to_string()),
+        )?;
+        return Ok((clear_csrf, denied).into_response());
+    }
+
+    db::consent::grant_consent(
+        &conn,
+        &realm_obj.id,
+        &session.user_id,
+        &client.client_id,
+        q.scope.as_deref().unwrap_or_default(),
+    )?;
+
+    let redirect = generate_auth_code_redirect(&conn, &state, &realm, &realm_obj.id, &q, &session)?;
+    Ok((clear_csrf, redirect).into_response())
+}
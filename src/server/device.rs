@@ -0,0 +1,436 @@
+use askama::Template;
+use axum::extract::{ConnectInfo, Path, Query, State};
+use axum::http::header::SET_COOKIE;
+use axum::http::HeaderMap;
+use axum::response::{Html, IntoResponse, Response};
+use axum::Form;
+use axum::Json;
+use chrono::{Duration, Utc};
+use rand::Rng;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+
+use super::authorize::{
+    authenticate_user, clear_csrf_cookie, create_session, csrf_cookie, generate_random_token,
+    session_from_cookie, verify_csrf,
+};
+use super::client_auth::{authenticate_client, ClientAuthForm};
+use super::consent::{consent_required, render_consent_page};
+use super::error::AppError;
+use super::scope::{describe, downscope};
+use super::token::{grant_allowed, DEVICE_CODE_GRANT};
+use super::AppState;
+use crate::crypto::csrf;
+use crate::crypto::token::hex_encode;
+use crate::db;
+use crate::models::{Client, DeviceCode, DeviceCodeStatus};
+
+/// Consonants only, so user codes can't spell words and are easy to read
+/// aloud (RFC 8628 §6.1).
+const USER_CODE_ALPHABET: &[u8] = b"BCDFGHJKLMNPQRSTVWXZ";
+const USER_CODE_LENGTH: usize = 8;
+
+/// Wrong user codes one address may enter within the window before it is
+/// turned away, so that codes can't be guessed (RFC 8628 §5.1).
+const MAX_USER_CODE_FAILURES: i64 = 10;
+const USER_CODE_FAILURE_WINDOW_SECS: i64 = 15 * 60;
+
+#[derive(Debug, Deserialize)]
+pub struct DeviceAuthorizationRequest {
+    pub scope: Option<String>,
+    #[serde(flatten)]
+    pub client_auth: ClientAuthForm,
+}
+
+/// POST /realms/{realm}/device_authorization — RFC 8628 device authorization request
+pub async fn device_authorization(
+    State(state): State<AppState>,
+    Path(realm): Path<String>,
+    headers: HeaderMap,
+    Form(form): Form<DeviceAuthorizationRequest>,
+) -> Result<Json<Value>, AppError> {
+    let conn = state
+        .db
+        .lock()
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let realm_obj = db::realm::get_realm_by_name(&conn, &realm)?
+        .ok_or_else(|| AppError::NotFound(format!("realm '{realm}' not found")))?;
+
+    let issuer = format!("{}/realms/{}", state.config.issuer_base_url, realm);
+    let client = authenticate_client(
+        &conn,
+        &realm_obj.id,
+        &issuer,
+        &format!("{issuer}/device_authorization"),
+        &headers,
+        &form.client_auth,
+    )?;
+
+    if !grant_allowed(&client, DEVICE_CODE_GRANT) {
+        return Err(AppError::BadRequest(
+            "unauthorized_client",
+            "client is not registered for the device_code grant".to_string(),
+        ));
+    }
+
+    let device_code = generate_random_token();
+    let device_code_hash = hex_encode(&Sha256::digest(device_code.as_bytes()));
+    let user_code = generate_user_code();
+    let expires_in = state.config.device_code_lifetime_secs;
+    let interval = state.config.device_poll_interval_secs;
+
+    db::device_code::insert_device_code(
+        &conn,
+        &db::device_code::NewDeviceCode {
+            realm_id: &realm_obj.id,
+            client_id: &client.client_id,
+            device_code_hash: &device_code_hash,
+            user_code: &user_code,
+            scopes: &downscope(
+                form.scope.as_deref().unwrap_or("openid"),
+                &client.allowed_scopes,
+            ),
+            interval_secs: interval as i64,
+            expires_at: Utc::now() + Duration::seconds(expires_in as i64),
+        },
+    )?;
+
+    let display_code = format_user_code(&user_code);
+    let verification_uri = format!("{issuer}/device");
+    let mut complete = url::Url::parse(&verification_uri)
+        .map_err(|e| AppError::Internal(format!("invalid issuer URL: {e}")))?;
+    complete
+        .query_pairs_mut()
+        .append_pair("user_code", &display_code);
+
+    Ok(Json(json!({
+        "device_code": device_code,
+        "user_code": display_code,
+        "verification_uri": verification_uri,
+        "verification_uri_complete": complete.to_string(),
+        "expires_in": expires_in,
+        "interval": interval,
+    })))
+}
+
+#[derive(Template)]
+#[template(path = "device.html")]
+struct DeviceTemplate {
+    realm_name: String,
+    error_message: Option<String>,
+    message: Option<String>,
+    csrf_token: String,
+    user_code: String,
+    show_login: bool,
+    /// Client whose request the code belongs to, once the code is known.
+    client_name: Option<String>,
+    /// What the device asks for, described for the user.
+    scopes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeviceQuery {
+    pub user_code: Option<String>,
+}
+
+/// GET /realms/{realm}/device — ask the user to confirm the code shown on their device
+pub async fn device_get(
+    State(state): State<AppState>,
+    Path(realm): Path<String>,
+    Query(q): Query<DeviceQuery>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let conn = state
+        .db
+        .lock()
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let realm_obj = db::realm::get_realm_by_name(&conn, &realm)?
+        .ok_or_else(|| AppError::NotFound(format!("realm '{realm}' not found")))?;
+
+    let signed_in = session_from_cookie(&conn, &realm, &realm_obj.id, &headers).is_some();
+    let Some(user_code) = q.user_code.filter(|code| !code.is_empty()) else {
+        return render_device_form(&realm, "", !signed_in, None, None);
+    };
+    match pending_authorization(&conn, &realm_obj.id, &peer, &user_code)? {
+        Ok(pending) => render_device_form(&realm, &user_code, !signed_in, Some(&pending), None),
+        Err(msg) => render_device_form(&realm, &user_code, !signed_in, None, Some(msg)),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeviceForm {
+    pub csrf_token: String,
+    pub user_code: String,
+    pub action: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Set once the user has been shown the client and what it asks for.
+    #[serde(default)]
+    pub consented: bool,
+}
+
+/// POST /realms/{realm}/device — approve or deny a device for the signed-in user
+pub async fn device_post(
+    State(state): State<AppState>,
+    Path(realm): Path<String>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Form(form): Form<DeviceForm>,
+) -> Result<Response, AppError> {
+    let conn = state
+        .db
+        .lock()
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let realm_obj = db::realm::get_realm_by_name(&conn, &realm)?
+        .ok_or_else(|| AppError::NotFound(format!("realm '{realm}' not found")))?;
+
+    let session = session_from_cookie(&conn, &realm, &realm_obj.id, &headers);
+
+    if !verify_csrf(&headers, &realm, &form.csrf_token) {
+        return render_device_form(
+            &realm,
+            &form.user_code,
+            session.is_none(),
+            None,
+            Some("Invalid request. Please try again."),
+        );
+    }
+
+    let pending = match pending_authorization(&conn, &realm_obj.id, &peer, &form.user_code)? {
+        Ok(pending) => pending,
+        Err(msg) => {
+            return render_device_form(&realm, &form.user_code, session.is_none(), None, Some(msg))
+        }
+    };
+
+    // Use the existing session, or sign the user in with the submitted credentials
+    let mut session_cookie = None;
+    let session = match session {
+        Some(s) => s,
+        None => {
+            let username = form.username.as_deref().unwrap_or_default();
+            let password = form.password.as_deref().unwrap_or_default();
+            let Some(user) = authenticate_user(&conn, &realm_obj.id, username, password)? else {
+                return render_device_form(
+                    &realm,
+                    &form.user_code,
+                    true,
+                    Some(&pending),
+                    Some("Invalid username or password"),
+                );
+            };
+            let (session, cookie) = create_session(&conn, &state, &realm, &realm_obj.id, &user.id)?;
+            session_cookie = Some(cookie);
+            session
+        }
+    };
+
+    let (device_code, client) = pending;
+    if form.action == "approve" {
+        // A typed-in code is only approved once the user has seen which
+        // client it connects and what that client gets
+        if !form.consented {
+            let fields = vec![
+                ("user_code", form.user_code.clone()),
+                ("consented", "true".to_string()),
+            ];
+            return render_consent_page(
+                &realm,
+                &client,
+                &device_code.scopes,
+                "device",
+                fields,
+                session_cookie,
+            );
+        }
+        // Third-party devices get the same consent check as browser sign-ins
+        if consent_required(&conn, &client, &session.user_id, &device_code.scopes, None)? {
+            db::consent::grant_consent(
+                &conn,
+                &realm_obj.id,
+                &session.user_id,
+                &client.client_id,
+                &device_code.scopes,
+            )?;
+        }
+    }
+
+    let (status, message) = if form.action == "approve" {
+        (
+            DeviceCodeStatus::Approved,
+            "Device connected. You can return to your device.",
+        )
+    } else {
+        (
+            DeviceCodeStatus::Denied,
+            "Access denied. The device was not connected.",
+        )
+    };
+    db::device_code::set_device_code_status(&conn, &device_code.id, status, &session)?;
+    tracing::info!(
+        "Device authorization for client '{}' {}",
+        device_code.client_id,
+        status.as_str()
+    );
+
+    let tmpl = DeviceTemplate {
+        realm_name: realm.clone(),
+        error_message: None,
+        message: Some(message.to_string()),
+        csrf_token: String::new(),
+        user_code: String::new(),
+        show_login: false,
+        client_name: None,
+        scopes: Vec::new(),
+    };
+    let html = tmpl
+        .render()
+        .map_err(|e: askama::Error| AppError::Internal(e.to_string()))?;
+
+    let mut cookies = vec![(SET_COOKIE, clear_csrf_cookie(&realm))];
+    if let Some(cookie) = session_cookie {
+        cookies.push((SET_COOKIE, cookie));
+    }
+    Ok((axum::response::AppendHeaders(cookies), Html(html)).into_response())
+}
+
+/// The pending authorization `user_code` names, with the client that asked
+/// for it, or what to tell the user. Wrong codes are counted per address,
+/// and an address with too many is refused until they age out.
+fn pending_authorization(
+    conn: &rusqlite::Connection,
+    realm_id: &str,
+    peer: &SocketAddr,
+    user_code: &str,
+) -> Result<Result<(DeviceCode, Client), &'static str>, AppError> {
+    let client_ip = peer.ip().to_string();
+    let since = Utc::now() - Duration::seconds(USER_CODE_FAILURE_WINDOW_SECS);
+    if db::device_code::count_user_code_failures(conn, realm_id, &client_ip, since)?
+        >= MAX_USER_CODE_FAILURES
+    {
+        return Ok(Err(
+            "Too many incorrect codes. Please wait a few minutes and try again.",
+        ));
+    }
+
+    let user_code = normalize_user_code(user_code);
+    let pending = match db::device_code::get_pending_by_user_code(conn, realm_id, &user_code)? {
+        Some(device_code) => {
+            db::client::get_client_by_client_id(conn, realm_id, &device_code.client_id)?
+                .map(|client| (device_code, client))
+        }
+        None => None,
+    };
+    match pending {
+        Some(pending) => Ok(Ok(pending)),
+        None => {
+            db::device_code::record_user_code_failure(conn, realm_id, &client_ip)?;
+            Ok(Err("That code is invalid or has expired."))
+        }
+    }
+}
+
+/// Render the device page. With a `pending` authorization it names the
+/// client and what it asks for, and approving needs no further confirmation.
+fn render_device_form(
+    realm: &str,
+    user_code: &str,
+    show_login: bool,
+    pending: Option<&(DeviceCode, Client)>,
+    error_msg: Option<&str>,
+) -> Result<Response, AppError> {
+    let csrf_token = csrf::generate_csrf_token();
+    let csrf_cookie = csrf_cookie(realm, &csrf_token);
+
+    let tmpl = DeviceTemplate {
+        realm_name: realm.to_string(),
+        error_message: error_msg.map(str::to_string),
+        message: None,
+        csrf_token,
+        user_code: user_code.to_string(),
+        show_login,
+        client_name: pending.map(|(_, client)| {
+            client
+                .display_name
+                .clone()
+                .unwrap_or_else(|| client.client_id.clone())
+        }),
+        scopes: pending
+            .map(|(device_code, _)| {
+                device_code
+                    .scopes
+                    .split_whitespace()
+                    .map(describe)
+                    .collect()
+            })
+            .unwrap_or_default(),
+    };
+
+    let html = tmpl
+        .render()
+        .map_err(|e: askama::Error| AppError::Internal(e.to_string()))?;
+    Ok(([(SET_COOKIE, csrf_cookie)], Html(html)).into_response())
+}
+
+fn generate_user_code() -> String {
+    let mut rng = rand::thread_rng();
+    (0..USER_CODE_LENGTH)
+        .map(|_| USER_CODE_ALPHABET[rng.gen_range(0..USER_CODE_ALPHABET.len())] as char)
+        .collect()
+}
+
+/// Display a user code as `XXXX-XXXX`.
+fn format_user_code(code: &str) -> String {
+    let (a, b) = code.split_at(code.len() / 2);
+    format!("{a}-{b}")
+}
+
+/// Accept codes typed with lowercase letters, dashes or spaces.
+fn normalize_user_code(input: &str) -> String {
+    input
+        .chars()
+        .map(|c| c.to_ascii_uppercase())
+        .filter(|c| c.is_ascii() && USER_CODE_ALPHABET.contains(&(*c as u8)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_accepts_lowercase_dashes_and_spaces() {
+        assert_eq!(normalize_user_code("QKFF-GBMM"), "QKFFGBMM");
+        assert_eq!(normalize_user_code("qkff-gbmm"), "QKFFGBMM");
+        assert_eq!(normalize_user_code(" qkff gbmm\n"), "QKFFGBMM");
+        assert_eq!(normalize_user_code(""), "");
+    }
+
+    #[test]
+    fn normalize_drops_characters_outside_the_alphabet() {
+        // Vowels, Y and digits are never part of a code
+        assert_eq!(normalize_user_code("BAEIOUY-0159Z"), "BZ");
+        // Non-ASCII characters whose low byte is an alphabet letter ('ł' is
+        // U+0142) must not slip through
+        assert_eq!(normalize_user_code("\u{142}BCD"), "BCD");
+    }
+
+    #[test]
+    fn format_splits_the_code_in_half() {
+        assert_eq!(format_user_code("QKFFGBMM"), "QKFF-GBMM");
+        assert_eq!(format_user_code(""), "-");
+    }
+
+    #[test]
+    fn generated_codes_survive_formatting() {
+        for _ in 0..100 {
+            let code = generate_user_code();
+            assert_eq!(code.len(), USER_CODE_LENGTH);
+            assert!(code.bytes().all(|b| USER_CODE_ALPHABET.contains(&b)));
+            assert_eq!(normalize_user_code(&format_user_code(&code)), code);
+        }
+    }
+}
@@ -3,6 +3,7 @@ use axum::Json;
 use serde_json::{json, Value};
 
 use super::error::AppError;
+use super::response_mode::RESPONSE_MODES;
 use super::AppState;
 use crate::db;
 
@@ -15,23 +16,55 @@ pub async fn openid_configuration(
         .db
         .lock()
         .map_err(|e| AppError::Internal(e.to_string()))?;
-    let _realm = db::realm::get_realm_by_name(&conn, &realm)?
+    let realm_obj = db::realm::get_realm_by_name(&conn, &realm)?
         .ok_or_else(|| AppError::NotFound(format!("realm '{realm}' not found")))?;
 
     let issuer = format!("{}/realms/{}", state.config.issuer_base_url, realm);
 
-    Ok(Json(json!({
+    let mut config = json!({
         "issuer": issuer,
         "authorization_endpoint": format!("{}/authorize", issuer),
         "token_endpoint": format!("{}/token", issuer),
+        "pushed_authorization_request_endpoint": format!("{}/par", issuer),
+        "require_pushed_authorization_requests": false,
+        "request_parameter_supported": true,
+        "request_uri_parameter_supported": true,
+        "require_request_uri_registration": true,
+        "request_object_signing_alg_values_supported": ["EdDSA", "ES256", "ES384", "RS256", "RS384", "RS512", "PS256", "PS384", "PS512"],
+        "device_authorization_endpoint": format!("{}/device_authorization", issuer),
         "userinfo_endpoint": format!("{}/userinfo", issuer),
         "jwks_uri": format!("{}/jwks", issuer),
+        "end_session_endpoint": format!("{}/logout", issuer),
+        "backchannel_logout_supported": true,
+        "backchannel_logout_session_supported": true,
+        "frontchannel_logout_supported": true,
+        "frontchannel_logout_session_supported": true,
+        "revocation_endpoint": format!("{}/revoke", issuer),
+        "introspection_endpoint": format!("{}/introspect", issuer),
+        "prompt_values_supported": ["none", "login", "consent"],
         "response_types_supported": ["code"],
-        "subject_types_supported": ["public"],
+        "response_modes_supported": RESPONSE_MODES,
+        "authorization_signing_alg_values_supported": ["EdDSA"],
+        "subject_types_supported": ["public", "pairwise"],
         "id_token_signing_alg_values_supported": ["EdDSA"],
         "scopes_supported": ["openid", "profile", "email"],
-        "token_endpoint_auth_methods_supported": ["none"],
-        "grant_types_supported": ["authorization_code", "refresh_token"],
+        "claims_supported": ["sub", "iss", "aud", "exp", "iat", "auth_time", "nonce", "sid", "preferred_username", "email"],
+        "token_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post", "private_key_jwt", "none"],
+        "token_endpoint_auth_signing_alg_values_supported": ["EdDSA", "ES256", "ES384", "RS256", "RS384", "RS512", "PS256", "PS384", "PS512"],
+        "revocation_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post", "private_key_jwt", "none"],
+        "introspection_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post", "private_key_jwt"],
+        "grant_types_supported": [
+            "authorization_code",
+            "refresh_token",
+            "client_credentials",
+            "urn:ietf:params:oauth:grant-type:device_code",
+            "urn:ietf:params:oauth:grant-type:token-exchange",
+        ],
         "code_challenge_methods_supported": ["S256"],
-    })))
+        "dpop_signing_alg_values_supported": ["EdDSA", "ES256", "ES384", "RS256", "PS256"],
+    });
+    if realm_obj.registration_enabled {
+        config["registration_endpoint"] = json!(format!("{}/register", issuer));
+    }
+    Ok(Json(config))
 }
@@ -0,0 +1,132 @@
+use axum::http::HeaderMap;
+use chrono::{DateTime, Duration, Utc};
+
+use super::error::AppError;
+use crate::crypto::dpop;
+use crate::crypto::token::Confirmation;
+use crate::db;
+
+/// How a request presented its access token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum TokenScheme {
+    Bearer,
+    Dpop,
+}
+
+/// An access token from the `Authorization` header.
+pub(super) struct PresentedToken {
+    pub scheme: TokenScheme,
+    pub token: String,
+}
+
+/// Verify the request's DPoP proof, if it sent one, and return the
+/// thumbprint of the key that signed it. Each proof is accepted only once.
+pub(super) fn dpop_key(
+    conn: &rusqlite::Connection,
+    realm_id: &str,
+    headers: &HeaderMap,
+    method: &str,
+    url: &str,
+    access_token: Option<&str>,
+) -> Result<Option<String>, AppError> {
+    let mut proofs = headers.get_all("DPoP").iter();
+    let Some(proof) = proofs.next() else {
+        return Ok(None);
+    };
+    if proofs.next().is_some() {
+        return Err(AppError::BadRequest(
+            "invalid_dpop_proof",
+            "invalid DPoP proof".to_string(),
+        ));
+    }
+    let proof = proof.to_str().map_err(|_| {
+        AppError::BadRequest("invalid_dpop_proof", "invalid DPoP proof".to_string())
+    })?;
+
+    let proof = match dpop::verify_proof(proof, method, url, access_token) {
+        Ok(proof) => proof,
+        Err(e) => {
+            tracing::warn!("DPoP proof rejected: {e}");
+            return Err(AppError::BadRequest(
+                "invalid_dpop_proof",
+                "invalid DPoP proof".to_string(),
+            ));
+        }
+    };
+
+    let expires_at = DateTime::<Utc>::from_timestamp(proof.iat, 0).unwrap_or_else(Utc::now)
+        + Duration::seconds(dpop::MAX_PROOF_AGE_SECS);
+    let replay_scope = format!("dpop:{}", proof.jkt);
+    if !db::jti::record_jti(conn, realm_id, &replay_scope, &proof.jti, expires_at)? {
+        tracing::warn!("Replayed DPoP proof for key {}", proof.jkt);
+        return Err(AppError::BadRequest(
+            "invalid_dpop_proof",
+            "invalid DPoP proof".to_string(),
+        ));
+    }
+    Ok(Some(proof.jkt))
+}
+
+/// Split the `Authorization` header into its scheme and access token.
+pub(super) fn extract_access_token(headers: &HeaderMap) -> Result<PresentedToken, AppError> {
+    let auth = headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .ok_or(AppError::MissingToken)?;
+
+    let (scheme, token) = if let Some(token) = auth.strip_prefix("Bearer ") {
+        (TokenScheme::Bearer, token)
+    } else if let Some(token) = auth.strip_prefix("DPoP ") {
+        (TokenScheme::Dpop, token)
+    } else {
+        return Err(AppError::Unauthorized(
+            "invalid_request",
+            "expected Bearer or DPoP token".to_string(),
+        ));
+    };
+    Ok(PresentedToken {
+        scheme,
+        token: token.to_string(),
+    })
+}
+
+/// Enforce a token's DPoP binding at a resource endpoint: a bound token must
+/// arrive with the DPoP scheme and a proof signed by its key, so a stolen
+/// token is useless without the key. Unbound tokens stay plain bearer tokens.
+pub(super) fn check_token_binding(
+    conn: &rusqlite::Connection,
+    realm_id: &str,
+    headers: &HeaderMap,
+    method: &str,
+    url: &str,
+    presented: &PresentedToken,
+    cnf: Option<&Confirmation>,
+) -> Result<(), AppError> {
+    match (presented.scheme, cnf) {
+        (TokenScheme::Bearer, None) => Ok(()),
+        (TokenScheme::Bearer, Some(_)) => Err(AppError::Unauthorized(
+            "invalid_token",
+            "DPoP-bound token must be sent with the DPoP scheme".to_string(),
+        )),
+        (TokenScheme::Dpop, None) => Err(AppError::Unauthorized(
+            "invalid_token",
+            "access token is not DPoP-bound".to_string(),
+        )),
+        (TokenScheme::Dpop, Some(cnf)) => {
+            let jkt = match dpop_key(conn, realm_id, headers, method, url, Some(&presented.token)) {
+                Ok(jkt) => jkt,
+                Err(AppError::BadRequest(error, msg)) => {
+                    return Err(AppError::Unauthorized(error, msg))
+                }
+                Err(e) => return Err(e),
+            };
+            if jkt.as_deref() != Some(cnf.jkt.as_str()) {
+                return Err(AppError::Unauthorized(
+                    "invalid_dpop_proof",
+                    "DPoP proof is not signed by the token's key".to_string(),
+                ));
+            }
+            Ok(())
+        }
+    }
+}
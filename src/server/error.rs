@@ -1,3 +1,4 @@
+use axum::http::header::WWW_AUTHENTICATE;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use serde_json::json;
@@ -7,11 +8,24 @@ pub enum AppError {
     #[error("not found: {0}")]
     NotFound(String),
 
-    #[error("bad request: {0}")]
-    BadRequest(String),
+    /// An OAuth error response (RFC 6749 §5.2): a registered error code such
+    /// as `invalid_grant` or `invalid_scope`, and a human-readable description.
+    #[error("{0}: {1}")]
+    BadRequest(&'static str, String),
 
-    #[error("unauthorized: {0}")]
-    Unauthorized(String),
+    /// Client authentication failed (RFC 6749 §5.2 `invalid_client`).
+    #[error("invalid client: {0}")]
+    InvalidClient(String),
+
+    /// A protected resource refused the access token (RFC 6750 §3), e.g.
+    /// `invalid_token` or `insufficient_scope`.
+    #[error("{0}: {1}")]
+    Unauthorized(&'static str, String),
+
+    /// A protected resource was requested without any access token; the
+    /// challenge carries no error code (RFC 6750 §3.1).
+    #[error("missing access token")]
+    MissingToken,
 
     #[error("internal error: {0}")]
     Internal(String),
@@ -19,20 +33,54 @@ pub enum AppError {
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let (status, message) = match &self {
-            AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg.clone()),
-            AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg.clone()),
-            AppError::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, msg.clone()),
+        let (status, error, description) = match self {
+            AppError::NotFound(msg) => (StatusCode::NOT_FOUND, "not_found", msg),
+            AppError::BadRequest(error, msg) => (StatusCode::BAD_REQUEST, error, msg),
+            AppError::InvalidClient(msg) => {
+                let body = json!({ "error": "invalid_client", "error_description": msg });
+                return (
+                    StatusCode::UNAUTHORIZED,
+                    [(WWW_AUTHENTICATE, "Basic".to_string())],
+                    axum::Json(body),
+                )
+                    .into_response();
+            }
+            AppError::Unauthorized(error, msg) => {
+                let status = match error {
+                    "invalid_request" => StatusCode::BAD_REQUEST,
+                    "insufficient_scope" => StatusCode::FORBIDDEN,
+                    _ => StatusCode::UNAUTHORIZED,
+                };
+                // Proof problems are challenged under the DPoP scheme (RFC 9449 §7.1)
+                let scheme = match error {
+                    "invalid_dpop_proof" => "DPoP",
+                    _ => "Bearer",
+                };
+                let challenge = format!(
+                    "{scheme} error=\"{error}\", error_description=\"{}\"",
+                    msg.replace(['"', '\\'], "'")
+                );
+                let body = json!({ "error": error, "error_description": msg });
+                return (status, [(WWW_AUTHENTICATE, challenge)], axum::Json(body)).into_response();
+            }
+            AppError::MissingToken => {
+                return (
+                    StatusCode::UNAUTHORIZED,
+                    [(WWW_AUTHENTICATE, "Bearer".to_string())],
+                )
+                    .into_response();
+            }
             AppError::Internal(msg) => {
                 tracing::error!("Internal error: {msg}");
                 (
                     StatusCode::INTERNAL_SERVER_ERROR,
+                    "server_error",
                     "internal server error".to_string(),
                 )
             }
         };
 
-        let body = json!({ "error": message });
+        let body = json!({ "error": error, "error_description": description });
         (status, axum::Json(body)).into_response()
     }
 }
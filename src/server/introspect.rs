@@ -0,0 +1,155 @@
+use axum::extract::{Path, State};
+use axum::http::HeaderMap;
+use axum::Form;
+use axum::Json;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use super::client_auth::{authenticate_client, ClientAuthForm};
+use super::error::AppError;
+use super::subject::{resolve_subject, subject_for};
+use super::AppState;
+use crate::crypto::{keys, token as jwt};
+use crate::db;
+use crate::models::ClientType;
+
+#[derive(Debug, Deserialize)]
+pub struct IntrospectRequest {
+    pub token: String,
+    pub token_type_hint: Option<String>,
+    #[serde(flatten)]
+    pub client_auth: ClientAuthForm,
+}
+
+/// POST /realms/{realm}/introspect — RFC 7662 token introspection
+pub async fn introspect(
+    State(state): State<AppState>,
+    Path(realm): Path<String>,
+    headers: HeaderMap,
+    Form(form): Form<IntrospectRequest>,
+) -> Result<Json<Value>, AppError> {
+    let conn = state
+        .db
+        .lock()
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let realm_obj = db::realm::get_realm_by_name(&conn, &realm)?
+        .ok_or_else(|| AppError::NotFound(format!("realm '{realm}' not found")))?;
+
+    let issuer = format!("{}/realms/{}", state.config.issuer_base_url, realm);
+    let client = authenticate_client(
+        &conn,
+        &realm_obj.id,
+        &issuer,
+        &format!("{issuer}/introspect"),
+        &headers,
+        &form.client_auth,
+    )?;
+    if client.client_type != ClientType::Confidential {
+        return Err(AppError::InvalidClient(
+            "introspection requires a confidential client".to_string(),
+        ));
+    }
+
+    let response = if form.token_type_hint.as_deref() == Some("refresh_token") {
+        match introspect_refresh_token(&conn, &realm_obj.id, &issuer, &form.token)? {
+            Some(v) => Some(v),
+            None => introspect_access_token(&conn, &realm_obj.id, &issuer, &form.token)?,
+        }
+    } else {
+        match introspect_access_token(&conn, &realm_obj.id, &issuer, &form.token)? {
+            Some(v) => Some(v),
+            None => introspect_refresh_token(&conn, &realm_obj.id, &issuer, &form.token)?,
+        }
+    };
+
+    Ok(Json(response.unwrap_or_else(|| json!({ "active": false }))))
+}
+
+/// Verify an access token issued by this realm, for the realm itself or for
+/// any of its APIs, against each of the realm's active keys.
+pub(super) fn decode_realm_access_token(
+    conn: &rusqlite::Connection,
+    realm_id: &str,
+    issuer: &str,
+    token: &str,
+) -> Result<Option<jwt::AccessTokenClaims>, AppError> {
+    let apis = db::resource_server::list_resource_servers(conn, realm_id)?;
+    let mut audiences = vec![issuer];
+    audiences.extend(apis.iter().map(|api| api.identifier.as_str()));
+
+    let keys = db::signing_key::get_all_active_keys(conn, realm_id)?;
+    for key in keys {
+        let decoding_key = keys::decoding_key_from_pem(&key.public_key_pem)
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        if let Ok(claims) = jwt::decode_access_token(token, &decoding_key, issuer, &audiences) {
+            return Ok(Some(claims));
+        }
+    }
+    Ok(None)
+}
+
+fn introspect_access_token(
+    conn: &rusqlite::Connection,
+    realm_id: &str,
+    issuer: &str,
+    token: &str,
+) -> Result<Option<Value>, AppError> {
+    let Some(claims) = decode_realm_access_token(conn, realm_id, issuer, token)? else {
+        return Ok(None);
+    };
+
+    let mut response = json!({
+        "active": true,
+        "token_type": "Bearer",
+        "scope": claims.scope,
+        "client_id": claims.client_id,
+        "sub": claims.sub,
+        "aud": claims.aud,
+        "iss": claims.iss,
+        "exp": claims.exp,
+        "iat": claims.iat,
+        "jti": claims.jti,
+    });
+    if let Some(act) = &claims.act {
+        response["act"] = json!(act);
+    }
+    if let Some(cnf) = &claims.cnf {
+        response["token_type"] = json!("DPoP");
+        response["cnf"] = json!(cnf);
+    }
+    if let Some(user) = resolve_subject(conn, realm_id, &claims.sub)? {
+        response["username"] = json!(user.username);
+    }
+    Ok(Some(response))
+}
+
+fn introspect_refresh_token(
+    conn: &rusqlite::Connection,
+    realm_id: &str,
+    issuer: &str,
+    token: &str,
+) -> Result<Option<Value>, AppError> {
+    let token_hash = jwt::hex_encode(&Sha256::digest(token.as_bytes()));
+    let Some(refresh) = db::refresh_token::get_refresh_token(conn, realm_id, &token_hash)? else {
+        return Ok(None);
+    };
+
+    let sub = match db::client::get_client_by_client_id(conn, realm_id, &refresh.client_id)? {
+        Some(client) => subject_for(conn, &client, &refresh.user_id)?,
+        None => refresh.user_id.clone(),
+    };
+    let mut response = json!({
+        "active": true,
+        "scope": refresh.scopes,
+        "client_id": refresh.client_id,
+        "sub": sub,
+        "iss": issuer,
+        "exp": refresh.expires_at.timestamp(),
+        "iat": refresh.created_at.timestamp(),
+    });
+    if let Some(user) = db::user::get_user_by_id(conn, &refresh.user_id)? {
+        response["username"] = json!(user.username);
+    }
+    Ok(Some(response))
+}
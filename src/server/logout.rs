@@ -0,0 +1,241 @@
+use askama::Template;
+use axum::extract::{Path, Query, State};
+use axum::http::header::SET_COOKIE;
+use axum::http::HeaderMap;
+use axum::response::{AppendHeaders, Html, IntoResponse, Redirect, Response};
+use axum::Form;
+use serde::Deserialize;
+
+use super::authorize::{
+    clear_csrf_cookie, clear_session_cookie, csrf_cookie, render_error_page, session_from_cookie,
+    verify_csrf,
+};
+use super::error::AppError;
+use super::subject::resolve_subject;
+use super::AppState;
+use crate::crypto::{csrf, keys, token as jwt};
+use crate::db;
+use crate::models::Session;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct LogoutParams {
+    pub id_token_hint: Option<String>,
+    pub client_id: Option<String>,
+    pub post_logout_redirect_uri: Option<String>,
+    pub state: Option<String>,
+    pub csrf_token: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "logout.html")]
+struct LogoutTemplate {
+    realm_name: String,
+    signed_out: bool,
+    csrf_token: String,
+    client_id: String,
+    post_logout_redirect_uri: String,
+    state: String,
+    /// Front-channel logout URIs to load in hidden iframes once signed out.
+    frontchannel_logout_uris: Vec<String>,
+    /// Where to continue once the iframes have loaded.
+    redirect_uri: Option<String>,
+}
+
+/// GET /realms/{realm}/logout — OIDC RP-Initiated Logout
+pub async fn logout_get(
+    State(state): State<AppState>,
+    Path(realm): Path<String>,
+    Query(params): Query<LogoutParams>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    end_session(&state, &realm, params, &headers, false)
+}
+
+/// POST /realms/{realm}/logout — RP-Initiated Logout, or the confirmation form
+pub async fn logout_post(
+    State(state): State<AppState>,
+    Path(realm): Path<String>,
+    headers: HeaderMap,
+    Form(params): Form<LogoutParams>,
+) -> Result<Response, AppError> {
+    end_session(&state, &realm, params, &headers, true)
+}
+
+fn end_session(
+    state: &AppState,
+    realm: &str,
+    params: LogoutParams,
+    headers: &HeaderMap,
+    is_post: bool,
+) -> Result<Response, AppError> {
+    // Hidden form fields round-trip absent values as empty strings
+    let non_empty = |v: Option<String>| v.filter(|s| !s.is_empty());
+    let id_token_hint = non_empty(params.id_token_hint);
+    let client_id = non_empty(params.client_id);
+    let post_logout_redirect_uri = non_empty(params.post_logout_redirect_uri);
+    let logout_state = non_empty(params.state);
+
+    let conn = state
+        .db
+        .lock()
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let realm_obj = db::realm::get_realm_by_name(&conn, realm)?
+        .ok_or_else(|| AppError::NotFound(format!("realm '{realm}' not found")))?;
+    let issuer = format!("{}/realms/{}", state.config.issuer_base_url, realm);
+
+    // Identify the relying party from the hint and/or client_id
+    let hint = match &id_token_hint {
+        Some(token) => match verify_id_token_hint(&conn, &realm_obj.id, &issuer, token)? {
+            Some(claims) => Some(claims),
+            None => return render_error_page("Invalid id_token_hint"),
+        },
+        None => None,
+    };
+    let client_id = match (hint.as_ref().map(|h| h.aud.clone()), client_id) {
+        (Some(aud), Some(id)) if aud != id => {
+            return render_error_page("client_id does not match id_token_hint");
+        }
+        (aud, id) => aud.or(id),
+    };
+    let client = match &client_id {
+        Some(id) => match db::client::get_client_by_client_id(&conn, &realm_obj.id, id)? {
+            Some(c) => Some(c),
+            None => return render_error_page("Unknown client_id"),
+        },
+        None => None,
+    };
+
+    if let Some(uri) = &post_logout_redirect_uri {
+        let registered = client
+            .as_ref()
+            .is_some_and(|c| c.post_logout_redirect_uris.contains(uri));
+        if !registered {
+            return render_error_page("post_logout_redirect_uri not registered");
+        }
+    }
+
+    // Without an id_token_hint any page could sign the user out with a link,
+    // so ask the user to confirm first. A hint for someone other than the
+    // signed-in user gets the same treatment.
+    let session = session_from_cookie(&conn, realm, &realm_obj.id, headers);
+    let hint_user = match &hint {
+        Some(h) => resolve_subject(&conn, &realm_obj.id, &h.sub)?.map(|u| u.id),
+        None => None,
+    };
+    let hint_matches = hint.as_ref().is_some_and(|_| {
+        session
+            .as_ref()
+            .is_none_or(|s| Some(&s.user_id) == hint_user.as_ref())
+    });
+    let confirmed = hint_matches
+        || (is_post
+            && params
+                .csrf_token
+                .as_deref()
+                .is_some_and(|t| verify_csrf(headers, realm, t)));
+    if !confirmed {
+        let csrf_token = csrf::generate_csrf_token();
+        let tmpl = LogoutTemplate {
+            realm_name: realm.to_string(),
+            signed_out: false,
+            csrf_token: csrf_token.clone(),
+            client_id: client_id.unwrap_or_default(),
+            post_logout_redirect_uri: post_logout_redirect_uri.unwrap_or_default(),
+            state: logout_state.unwrap_or_default(),
+            frontchannel_logout_uris: Vec::new(),
+            redirect_uri: None,
+        };
+        let html = tmpl
+            .render()
+            .map_err(|e: askama::Error| AppError::Internal(e.to_string()))?;
+        return Ok(([(SET_COOKIE, csrf_cookie(realm, &csrf_token))], Html(html)).into_response());
+    }
+
+    let mut frontchannel_logout_uris = Vec::new();
+    if let Some(session) = &session {
+        frontchannel_logout_uris = session_frontchannel_uris(&conn, session, &issuer)?;
+        let queued = db::session::end_session(&conn, session)?;
+        tracing::info!("Ended session in realm '{realm}' ({queued} back-channel logouts queued)");
+    }
+
+    let cookies = AppendHeaders([
+        (SET_COOKIE, clear_session_cookie(realm)),
+        (SET_COOKIE, clear_csrf_cookie(realm)),
+    ]);
+
+    let redirect_uri = match post_logout_redirect_uri {
+        Some(uri) => {
+            let mut redirect = url::Url::parse(&uri).map_err(|e| {
+                AppError::Internal(format!("invalid post_logout_redirect_uri: {e}"))
+            })?;
+            if let Some(s) = &logout_state {
+                redirect.query_pairs_mut().append_pair("state", s);
+            }
+            Some(redirect.to_string())
+        }
+        None => None,
+    };
+
+    // With no front-channel clients to notify there is no need for a page.
+    if let (Some(uri), true) = (&redirect_uri, frontchannel_logout_uris.is_empty()) {
+        return Ok((cookies, Redirect::to(uri)).into_response());
+    }
+
+    let tmpl = LogoutTemplate {
+        realm_name: realm.to_string(),
+        signed_out: true,
+        csrf_token: String::new(),
+        client_id: String::new(),
+        post_logout_redirect_uri: String::new(),
+        state: String::new(),
+        frontchannel_logout_uris,
+        redirect_uri,
+    };
+    let html = tmpl
+        .render()
+        .map_err(|e: askama::Error| AppError::Internal(e.to_string()))?;
+    Ok((cookies, Html(html)).into_response())
+}
+
+/// Front-channel logout URIs of the clients that took part in `session`,
+/// with `iss` and `sid` added (OIDC Front-Channel Logout §2).
+fn session_frontchannel_uris(
+    conn: &rusqlite::Connection,
+    session: &Session,
+    issuer: &str,
+) -> Result<Vec<String>, AppError> {
+    let mut uris = Vec::new();
+    for client_id in db::session::list_session_client_ids(conn, session)? {
+        let Some(uri) = db::client::get_client_by_client_id(conn, &session.realm_id, &client_id)?
+            .and_then(|c| c.frontchannel_logout_uri)
+        else {
+            continue;
+        };
+        match url::Url::parse(&uri) {
+            Ok(mut url) => {
+                url.query_pairs_mut()
+                    .append_pair("iss", issuer)
+                    .append_pair("sid", &session.id);
+                uris.push(url.to_string());
+            }
+            Err(e) => tracing::warn!("Invalid frontchannel_logout_uri for '{client_id}': {e}"),
+        }
+    }
+    Ok(uris)
+}
+
+fn verify_id_token_hint(
+    conn: &rusqlite::Connection,
+    realm_id: &str,
+    issuer: &str,
+    token: &str,
+) -> Result<Option<jwt::IdTokenHintClaims>, AppError> {
+    for key in db::signing_key::get_all_active_keys(conn, realm_id)? {
+        let decoding_key = keys::decoding_key_from_pem(&key.public_key_pem)
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        if let Ok(claims) = jwt::decode_id_token_hint(token, &decoding_key, issuer) {
+            return Ok(Some(claims));
+        }
+    }
+    Ok(None)
+}
@@ -1,8 +1,22 @@
 pub mod authorize;
+pub mod backchannel;
+pub mod client_auth;
+pub mod consent;
+pub mod device;
 pub mod discovery;
+pub mod dpop;
 pub mod error;
+pub mod introspect;
 pub mod jwks;
+pub mod logout;
+pub mod par;
 pub mod password;
+pub mod register;
+pub mod resource;
+pub mod response_mode;
+pub mod revoke;
+pub mod scope;
+pub mod subject;
 pub mod token;
 pub mod userinfo;
 
@@ -19,12 +33,16 @@ pub struct AppState {
     pub config: Arc<Config>,
 }
 
-pub fn build_router(config: Config, conn: Connection) -> Router {
-    let state = AppState {
-        db: Arc::new(Mutex::new(conn)),
-        config: Arc::new(config),
-    };
+impl AppState {
+    pub fn new(config: Config, conn: Connection) -> Self {
+        AppState {
+            db: Arc::new(Mutex::new(conn)),
+            config: Arc::new(config),
+        }
+    }
+}
 
+pub fn build_router(state: AppState) -> Router {
     Router::new()
         .route(
             "/realms/{realm}/.well-known/openid-configuration",
@@ -35,8 +53,34 @@ pub fn build_router(config: Config, conn: Connection) -> Router {
             "/realms/{realm}/authorize",
             get(authorize::authorize_get).post(authorize::authorize_post),
         )
+        .route(
+            "/realms/{realm}/par",
+            post(par::pushed_authorization_request),
+        )
+        .route("/realms/{realm}/consent", post(consent::consent_post))
         .route("/realms/{realm}/token", post(token::token))
+        .route(
+            "/realms/{realm}/device_authorization",
+            post(device::device_authorization),
+        )
+        .route(
+            "/realms/{realm}/device",
+            get(device::device_get).post(device::device_post),
+        )
+        .route("/realms/{realm}/revoke", post(revoke::revoke))
+        .route("/realms/{realm}/introspect", post(introspect::introspect))
         .route("/realms/{realm}/userinfo", get(userinfo::userinfo))
+        .route(
+            "/realms/{realm}/logout",
+            get(logout::logout_get).post(logout::logout_post),
+        )
+        .route("/realms/{realm}/register", post(register::register))
+        .route(
+            "/realms/{realm}/register/{client_id}",
+            get(register::read_registration)
+                .put(register::update_registration)
+                .delete(register::delete_registration),
+        )
         .route("/realms/{realm}/password", post(password::change_password))
         .layer(TraceLayer::new_for_http())
         .with_state(state)
@@ -0,0 +1,133 @@
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{Form, Json};
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use super::authorize::{
+    deserialize_max_age, generate_random_token, grant_openid_scope, load_request_object,
+    validate_authorize_params, AuthorizeQuery, PAR_REQUEST_URI_PREFIX,
+};
+use super::client_auth::{authenticate_client, ClientAuthForm};
+use super::error::AppError;
+use super::resource::find_resource;
+use super::AppState;
+use crate::crypto::token::hex_encode;
+use crate::db;
+
+/// The authorization request parameters, minus `client_id`, which is part of
+/// the client authentication. With a signed `request` object the inline
+/// parameters are ignored.
+#[derive(Debug, Deserialize)]
+pub struct PushedAuthorizationRequest {
+    #[serde(default)]
+    pub response_type: String,
+    #[serde(default)]
+    pub redirect_uri: String,
+    pub scope: Option<String>,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    pub nonce: Option<String>,
+    pub prompt: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_max_age")]
+    pub max_age: Option<u64>,
+    pub request: Option<String>,
+    pub request_uri: Option<String>,
+    pub resource: Option<String>,
+    pub response_mode: Option<String>,
+    #[serde(flatten)]
+    pub client_auth: ClientAuthForm,
+}
+
+/// POST /realms/{realm}/par — RFC 9126 pushed authorization request
+pub async fn pushed_authorization_request(
+    State(state): State<AppState>,
+    Path(realm): Path<String>,
+    headers: HeaderMap,
+    Form(form): Form<PushedAuthorizationRequest>,
+) -> Result<Response, AppError> {
+    let conn = state
+        .db
+        .lock()
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let realm_obj = db::realm::get_realm_by_name(&conn, &realm)?
+        .ok_or_else(|| AppError::NotFound(format!("realm '{realm}' not found")))?;
+
+    let issuer = format!("{}/realms/{}", state.config.issuer_base_url, realm);
+    let client = authenticate_client(
+        &conn,
+        &realm_obj.id,
+        &issuer,
+        &format!("{issuer}/par"),
+        &headers,
+        &form.client_auth,
+    )?;
+
+    // RFC 9126 §2.1: a pushed request can't itself refer to a pushed request
+    if form.request_uri.is_some() {
+        return Err(AppError::BadRequest(
+            "invalid_request",
+            "request_uri is not allowed in a pushed request".to_string(),
+        ));
+    }
+
+    let mut q = AuthorizeQuery {
+        response_type: form.response_type,
+        client_id: client.client_id.clone(),
+        redirect_uri: form.redirect_uri,
+        scope: form.scope,
+        state: form.state,
+        code_challenge: form.code_challenge,
+        code_challenge_method: form.code_challenge_method,
+        nonce: form.nonce,
+        prompt: form.prompt,
+        max_age: form.max_age,
+        request: None,
+        request_uri: None,
+        resource: form.resource,
+        response_mode: form.response_mode,
+    };
+    if let Some(request) = &form.request {
+        q = load_request_object(&conn, &realm_obj.id, &issuer, &client.client_id, request)?
+            .map_err(|msg| AppError::BadRequest("invalid_request_object", msg))?;
+    }
+    validate_authorize_params(&q)?;
+    if !client.redirect_uris.contains(&q.redirect_uri) {
+        return Err(AppError::BadRequest(
+            "invalid_request",
+            "redirect_uri not registered".to_string(),
+        ));
+    }
+    q.scope = Some(grant_openid_scope(
+        q.scope.as_deref().unwrap_or_default(),
+        &client,
+    )?);
+    find_resource(&conn, &realm_obj.id, q.resource.as_deref())?;
+
+    let params = serde_json::to_string(&q).map_err(|e| AppError::Internal(e.to_string()))?;
+    let request_uri = format!("{PAR_REQUEST_URI_PREFIX}{}", generate_random_token());
+    let request_uri_hash = hex_encode(&Sha256::digest(request_uri.as_bytes()));
+    let expires_in = state.config.par_lifetime_secs;
+
+    db::pushed_request::insert_pushed_request(
+        &conn,
+        &realm_obj.id,
+        &client.client_id,
+        &request_uri_hash,
+        &params,
+        Utc::now() + Duration::seconds(expires_in as i64),
+    )?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(json!({
+            "request_uri": request_uri,
+            "expires_in": expires_in,
+        })),
+    )
+        .into_response())
+}
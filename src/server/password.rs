@@ -4,7 +4,9 @@ use axum::Json;
 use serde::Deserialize;
 use serde_json::{json, Value};
 
+use super::dpop::{check_token_binding, extract_access_token};
 use super::error::AppError;
+use super::subject::resolve_subject;
 use super::AppState;
 use crate::crypto::{keys, password as pw, token as jwt};
 use crate::db;
@@ -21,8 +23,8 @@ pub async fn change_password(
     headers: HeaderMap,
     Json(body): Json<ChangePasswordRequest>,
 ) -> Result<Json<Value>, AppError> {
-    // Validate bearer token
-    let bearer = extract_bearer(&headers)?;
+    // Validate access token
+    let presented = extract_access_token(&headers)?;
 
     let conn = state
         .db
@@ -38,15 +40,32 @@ pub async fn change_password(
     let decoding_key = keys::decoding_key_from_pem(&signing_key.public_key_pem)
         .map_err(|e| AppError::Internal(e.to_string()))?;
 
-    let claims = jwt::decode_access_token(&bearer, &decoding_key, &issuer)
-        .map_err(|_| AppError::Unauthorized("invalid access token".to_string()))?;
+    let claims = jwt::decode_access_token(&presented.token, &decoding_key, &issuer, &[&issuer])
+        .map_err(|_| {
+            AppError::Unauthorized(
+                "invalid_token",
+                "invalid or expired access token".to_string(),
+            )
+        })?;
+    check_token_binding(
+        &conn,
+        &realm_obj.id,
+        &headers,
+        "POST",
+        &format!("{issuer}/password"),
+        &presented,
+        claims.cnf.as_ref(),
+    )?;
 
-    let user = db::user::get_user_by_id(&conn, &claims.sub)?
-        .ok_or_else(|| AppError::Internal("user not found".to_string()))?;
+    // Tokens from the client_credentials grant have no user behind them.
+    let user = resolve_subject(&conn, &realm_obj.id, &claims.sub)?.ok_or_else(|| {
+        AppError::Unauthorized("invalid_token", "access token has no user".to_string())
+    })?;
 
     // Verify current password
     if !pw::verify_password(&body.current_password, &user.password_hash) {
         return Err(AppError::BadRequest(
+            "invalid_request",
             "current password is incorrect".to_string(),
         ));
     }
@@ -58,16 +77,3 @@ pub async fn change_password(
 
     Ok(Json(json!({ "status": "password updated" })))
 }
-
-fn extract_bearer(headers: &HeaderMap) -> Result<String, AppError> {
-    let auth = headers
-        .get(axum::http::header::AUTHORIZATION)
-        .and_then(|v| v.to_str().ok())
-        .ok_or_else(|| AppError::Unauthorized("missing Authorization header".to_string()))?;
-
-    let token = auth
-        .strip_prefix("Bearer ")
-        .ok_or_else(|| AppError::Unauthorized("expected Bearer token".to_string()))?;
-
-    Ok(token.to_string())
-}
@@ -0,0 +1,415 @@
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use super::authorize::generate_random_token;
+use super::error::AppError;
+use super::response_mode::is_loopback;
+use super::scope::downscope;
+use super::token::DEVICE_CODE_GRANT;
+use super::userinfo::extract_bearer;
+use super::AppState;
+use crate::crypto::keys::parse_jwks;
+use crate::crypto::password::{generate_client_secret, hash_password};
+use crate::crypto::token::hex_encode;
+use crate::db;
+use crate::models::{Client, ClientType};
+
+/// Scopes a self-registered client may ask for.
+const REGISTRABLE_SCOPES: [&str; 3] = ["openid", "profile", "email"];
+
+/// Redirect URI schemes that would run or read content in the browser
+/// instead of reaching the client (OAuth security BCP §4.1).
+const FORBIDDEN_REDIRECT_SCHEMES: [&str; 6] =
+    ["javascript", "data", "file", "vbscript", "blob", "about"];
+
+/// Client metadata accepted at registration (RFC 7591 §2).
+#[derive(Debug, Deserialize)]
+pub struct ClientMetadata {
+    #[serde(default)]
+    pub redirect_uris: Vec<String>,
+    pub grant_types: Option<Vec<String>>,
+    pub token_endpoint_auth_method: Option<String>,
+    pub client_name: Option<String>,
+    pub jwks: Option<Value>,
+    pub scope: Option<String>,
+    /// `public` or `pairwise` (OIDC Registration §2)
+    pub subject_type: Option<String>,
+    /// Only sent on update, where it must name the client (RFC 7592 §2.2).
+    pub client_id: Option<String>,
+}
+
+/// Metadata checked and converted into what a client row stores.
+struct ValidMetadata {
+    client_type: ClientType,
+    needs_secret: bool,
+    jwks: Option<String>,
+    scopes: Option<Vec<String>>,
+    pairwise: bool,
+    grant_types: Vec<String>,
+}
+
+/// POST /realms/{realm}/register — RFC 7591 dynamic client registration
+pub async fn register(
+    State(state): State<AppState>,
+    Path(realm): Path<String>,
+    headers: HeaderMap,
+    Json(meta): Json<ClientMetadata>,
+) -> Result<Response, AppError> {
+    let initial_token = extract_bearer(&headers)?;
+
+    let conn = state
+        .db
+        .lock()
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let realm_obj = db::realm::get_realm_by_name(&conn, &realm)?
+        .ok_or_else(|| AppError::NotFound(format!("realm '{realm}' not found")))?;
+    if !realm_obj.registration_enabled {
+        return Err(AppError::NotFound(
+            "dynamic client registration is not enabled".to_string(),
+        ));
+    }
+
+    let valid = validate_metadata(&meta)?;
+
+    let initial_token_hash = hex_encode(&Sha256::digest(initial_token.as_bytes()));
+    if !db::initial_access_token::use_initial_access_token(
+        &conn,
+        &realm_obj.id,
+        &initial_token_hash,
+    )? {
+        return Err(AppError::Unauthorized(
+            "invalid_token",
+            "invalid initial access token".to_string(),
+        ));
+    }
+
+    let client_id = Uuid::new_v4().to_string();
+    let secret = valid.needs_secret.then(generate_client_secret);
+    let secret_hash = secret.as_deref().map(hash_password).transpose()?;
+    let registration_token = generate_random_token();
+    let registration_token_hash = hex_encode(&Sha256::digest(registration_token.as_bytes()));
+
+    let mut client = db::client::create_client(
+        &conn,
+        &db::client::NewClient {
+            realm_id: &realm_obj.id,
+            client_id: &client_id,
+            client_type: valid.client_type,
+            client_secret_hash: secret_hash.as_deref(),
+            jwks: valid.jwks.as_deref(),
+            jwks_path: None,
+            redirect_uris: &meta.redirect_uris,
+            post_logout_redirect_uris: &[],
+            request_uris: &[],
+            backchannel_logout_uri: None,
+            frontchannel_logout_uri: None,
+            display_name: meta.client_name.as_deref(),
+            // Self-registered clients are never trusted to skip consent
+            first_party: false,
+            require_par: false,
+            registration_token_hash: Some(&registration_token_hash),
+            pairwise: valid.pairwise,
+            sector_identifier: None,
+            grant_types: Some(&valid.grant_types),
+        },
+    )?;
+    if let Some(scopes) = valid.scopes {
+        db::client::update_client(
+            &conn,
+            &realm_obj.id,
+            &client_id,
+            &db::client::ClientUpdate {
+                allowed_scopes: Some(&scopes),
+                ..Default::default()
+            },
+        )?;
+        client.allowed_scopes = scopes;
+    }
+    tracing::info!("Registered client '{client_id}' in realm '{realm}'");
+
+    let issuer = format!("{}/realms/{}", state.config.issuer_base_url, realm);
+    let mut info = client_info(&issuer, &client);
+    info["registration_access_token"] = json!(registration_token);
+    if let Some(secret) = secret {
+        info["client_secret"] = json!(secret);
+        info["client_secret_expires_at"] = json!(0);
+    }
+    Ok((StatusCode::CREATED, Json(info)).into_response())
+}
+
+/// GET /realms/{realm}/register/{client_id} — RFC 7592 read
+pub async fn read_registration(
+    State(state): State<AppState>,
+    Path((realm, client_id)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, AppError> {
+    let conn = state
+        .db
+        .lock()
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let client = registered_client(&conn, &realm, &client_id, &headers)?;
+
+    let issuer = format!("{}/realms/{}", state.config.issuer_base_url, realm);
+    Ok(Json(client_info(&issuer, &client)))
+}
+
+/// PUT /realms/{realm}/register/{client_id} — RFC 7592 update. The metadata
+/// replaces what was registered; the authentication method can't change.
+pub async fn update_registration(
+    State(state): State<AppState>,
+    Path((realm, client_id)): Path<(String, String)>,
+    headers: HeaderMap,
+    Json(meta): Json<ClientMetadata>,
+) -> Result<Json<Value>, AppError> {
+    let conn = state
+        .db
+        .lock()
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let client = registered_client(&conn, &realm, &client_id, &headers)?;
+
+    if meta.client_id.as_deref() != Some(client_id.as_str()) {
+        return Err(AppError::BadRequest(
+            "invalid_client_metadata",
+            "client_id must match the registration being updated".to_string(),
+        ));
+    }
+    let mut meta = meta;
+    if meta.token_endpoint_auth_method.is_none() {
+        meta.token_endpoint_auth_method = Some(auth_method(&client).to_string());
+    }
+    let valid = validate_metadata(&meta)?;
+    if valid.client_type != client.client_type
+        || valid.needs_secret != client.client_secret_hash.is_some()
+    {
+        return Err(AppError::BadRequest(
+            "invalid_client_metadata",
+            "token_endpoint_auth_method cannot be changed".to_string(),
+        ));
+    }
+
+    db::client::update_client(
+        &conn,
+        &client.realm_id,
+        &client_id,
+        &db::client::ClientUpdate {
+            redirect_uris: Some(&meta.redirect_uris),
+            jwks: valid.jwks.as_deref(),
+            allowed_scopes: valid.scopes.as_deref(),
+            display_name: meta.client_name.as_deref(),
+            pairwise: Some(valid.pairwise),
+            grant_types: Some(&valid.grant_types),
+            ..Default::default()
+        },
+    )?;
+    let client = db::client::get_client_by_client_id(&conn, &client.realm_id, &client_id)?
+        .ok_or_else(|| AppError::Internal("updated client disappeared".to_string()))?;
+
+    let issuer = format!("{}/realms/{}", state.config.issuer_base_url, realm);
+    Ok(Json(client_info(&issuer, &client)))
+}
+
+/// DELETE /realms/{realm}/register/{client_id} — RFC 7592 deregistration
+pub async fn delete_registration(
+    State(state): State<AppState>,
+    Path((realm, client_id)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<StatusCode, AppError> {
+    let conn = state
+        .db
+        .lock()
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let client = registered_client(&conn, &realm, &client_id, &headers)?;
+
+    db::client::delete_client(&conn, &client.realm_id, &client_id)?;
+    tracing::info!("Deregistered client '{client_id}' in realm '{realm}'");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Look up a dynamically registered client by its registration access token.
+/// Every failure looks the same, so the endpoint doesn't reveal which
+/// client_ids exist.
+fn registered_client(
+    conn: &rusqlite::Connection,
+    realm: &str,
+    client_id: &str,
+    headers: &HeaderMap,
+) -> Result<Client, AppError> {
+    let token = extract_bearer(headers)?;
+    let token_hash = hex_encode(&Sha256::digest(token.as_bytes()));
+
+    let realm_obj = db::realm::get_realm_by_name(conn, realm)?
+        .ok_or_else(|| AppError::NotFound(format!("realm '{realm}' not found")))?;
+    db::client::get_client_by_client_id(conn, &realm_obj.id, client_id)?
+        .filter(|c| c.registration_token_hash.as_deref() == Some(token_hash.as_str()))
+        .ok_or_else(|| {
+            AppError::Unauthorized(
+                "invalid_token",
+                "invalid registration access token".to_string(),
+            )
+        })
+}
+
+fn validate_metadata(meta: &ClientMetadata) -> Result<ValidMetadata, AppError> {
+    let invalid = |msg: &str| AppError::BadRequest("invalid_client_metadata", msg.to_string());
+
+    let jwks = meta
+        .jwks
+        .as_ref()
+        .map(|jwks| {
+            let json = jwks.to_string();
+            parse_jwks(&json).map_err(|e| invalid(&format!("jwks: {e}")))?;
+            Ok::<_, AppError>(json)
+        })
+        .transpose()?;
+
+    let default_method = if jwks.is_some() {
+        "private_key_jwt"
+    } else {
+        "client_secret_basic"
+    };
+    let method = meta
+        .token_endpoint_auth_method
+        .as_deref()
+        .unwrap_or(default_method);
+    let (client_type, needs_secret) = match method {
+        "none" if jwks.is_none() => (ClientType::Public, false),
+        "client_secret_basic" | "client_secret_post" if jwks.is_none() => {
+            (ClientType::Confidential, true)
+        }
+        "private_key_jwt" if jwks.is_some() => (ClientType::Confidential, false),
+        "none" | "client_secret_basic" | "client_secret_post" => {
+            return Err(invalid("jwks is only used with private_key_jwt"))
+        }
+        "private_key_jwt" => return Err(invalid("private_key_jwt requires jwks")),
+        _ => return Err(invalid("unsupported token_endpoint_auth_method")),
+    };
+
+    let grant_types = meta
+        .grant_types
+        .clone()
+        .unwrap_or_else(|| vec!["authorization_code".to_string()]);
+    let supported = supported_grant_types(client_type);
+    if let Some(grant) = grant_types
+        .iter()
+        .find(|g| !supported.contains(&g.as_str()))
+    {
+        return Err(invalid(&format!("grant_type '{grant}' is not available")));
+    }
+
+    if grant_types.iter().any(|g| g == "authorization_code") && meta.redirect_uris.is_empty() {
+        return Err(AppError::BadRequest(
+            "invalid_redirect_uri",
+            "redirect_uris are required".to_string(),
+        ));
+    }
+    for uri in &meta.redirect_uris {
+        let parsed = url::Url::parse(uri).map_err(|_| {
+            AppError::BadRequest("invalid_redirect_uri", format!("'{uri}' is not a URL"))
+        })?;
+        if parsed.fragment().is_some() {
+            return Err(AppError::BadRequest(
+                "invalid_redirect_uri",
+                format!("'{uri}' must not have a fragment"),
+            ));
+        }
+        if FORBIDDEN_REDIRECT_SCHEMES.contains(&parsed.scheme()) {
+            return Err(AppError::BadRequest(
+                "invalid_redirect_uri",
+                format!("'{uri}' uses a forbidden scheme"),
+            ));
+        }
+        // Plain http only for native apps listening on loopback (RFC 8252 §7.3)
+        if parsed.scheme() == "http" && !is_loopback(&parsed) {
+            return Err(AppError::BadRequest(
+                "invalid_redirect_uri",
+                format!("'{uri}' must use https"),
+            ));
+        }
+    }
+
+    let scopes = match &meta.scope {
+        Some(scope) => {
+            let registrable: Vec<String> =
+                REGISTRABLE_SCOPES.iter().map(|s| s.to_string()).collect();
+            let granted = downscope(scope, &registrable);
+            if granted.is_empty() {
+                return Err(invalid("none of the requested scopes are available"));
+            }
+            Some(granted.split(' ').map(str::to_string).collect())
+        }
+        None => None,
+    };
+
+    // sector_identifier_uri isn't supported, so a pairwise client's sector is
+    // its own client_id: its redirect hosts prove nothing about who it is
+    let pairwise = match meta.subject_type.as_deref() {
+        None | Some("public") => false,
+        Some("pairwise") => true,
+        Some(_) => return Err(invalid("unsupported subject_type")),
+    };
+
+    Ok(ValidMetadata {
+        client_type,
+        needs_secret,
+        jwks,
+        scopes,
+        pairwise,
+        grant_types,
+    })
+}
+
+fn supported_grant_types(client_type: ClientType) -> Vec<&'static str> {
+    let mut grants = vec!["authorization_code", "refresh_token", DEVICE_CODE_GRANT];
+    if client_type == ClientType::Confidential {
+        grants.push("client_credentials");
+    }
+    grants
+}
+
+fn auth_method(client: &Client) -> &'static str {
+    if client.jwks.is_some() || client.jwks_path.is_some() {
+        "private_key_jwt"
+    } else if client.client_secret_hash.is_some() {
+        "client_secret_basic"
+    } else {
+        "none"
+    }
+}
+
+/// The registered metadata as returned by the registration endpoints.
+fn client_info(issuer: &str, client: &Client) -> Value {
+    let grant_types = client.grant_types.clone().unwrap_or_else(|| {
+        supported_grant_types(client.client_type)
+            .into_iter()
+            .map(str::to_string)
+            .collect()
+    });
+    let mut info = json!({
+        "client_id": client.client_id,
+        "client_id_issued_at": client.created_at.timestamp(),
+        "redirect_uris": client.redirect_uris,
+        "grant_types": grant_types,
+        "response_types": ["code"],
+        "token_endpoint_auth_method": auth_method(client),
+        "scope": client.allowed_scopes.join(" "),
+        "subject_type": if client.pairwise { "pairwise" } else { "public" },
+        "registration_client_uri": format!("{issuer}/register/{}", client.client_id),
+    });
+    if let Some(name) = &client.display_name {
+        info["client_name"] = json!(name);
+    }
+    if let Some(jwks) = client
+        .jwks
+        .as_deref()
+        .and_then(|j| serde_json::from_str::<Value>(j).ok())
+    {
+        info["jwks"] = jwks;
+    }
+    info
+}
@@ -0,0 +1,34 @@
+use super::error::AppError;
+use super::scope::downscope;
+use crate::db;
+use crate::models::ResourceServer;
+
+/// The API a `resource` parameter names (RFC 8707), or None when the request
+/// didn't name one. An API the realm doesn't know is `invalid_target`.
+pub(super) fn find_resource(
+    conn: &rusqlite::Connection,
+    realm_id: &str,
+    resource: Option<&str>,
+) -> Result<Option<ResourceServer>, AppError> {
+    let Some(identifier) = resource else {
+        return Ok(None);
+    };
+    match db::resource_server::get_resource_server(conn, realm_id, identifier)? {
+        Some(api) => Ok(Some(api)),
+        None => Err(AppError::BadRequest(
+            "invalid_target",
+            "unknown resource".to_string(),
+        )),
+    }
+}
+
+/// Narrow a grant's scopes to those the API understands, so a token for one
+/// API doesn't advertise scopes meant for another. APIs that declare no
+/// scopes take the grant as is.
+pub(super) fn resource_scope(scopes: &str, api: &ResourceServer) -> String {
+    if api.scopes.is_empty() {
+        scopes.to_string()
+    } else {
+        downscope(scopes, &api.scopes)
+    }
+}
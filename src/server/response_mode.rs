@@ -0,0 +1,181 @@
+use askama::Template;
+use axum::http::header::{CACHE_CONTROL, CONTENT_SECURITY_POLICY};
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use chrono::{Duration, Utc};
+use serde_json::{json, Value};
+
+use super::authorize::AuthorizeQuery;
+use super::error::AppError;
+use super::AppState;
+use crate::crypto::{keys, token as jwt};
+use crate::db;
+
+/// Ways an authorization response can reach the client: OAuth 2.0 Multiple
+/// Response Types, Form Post Response Mode, and JARM's signed variants.
+pub const RESPONSE_MODES: [&str; 7] = [
+    "query",
+    "fragment",
+    "form_post",
+    "jwt",
+    "query.jwt",
+    "fragment.jwt",
+    "form_post.jwt",
+];
+
+#[derive(Template)]
+#[template(path = "form_post.html")]
+struct FormPostTemplate {
+    redirect_uri: String,
+    params: Vec<(String, String)>,
+}
+
+/// Check the response mode before the user signs in or consents, so that a
+/// request that can't be answered never gets as far as issuing a code.
+pub(super) fn validate_response_mode(q: &AuthorizeQuery) -> Result<(), AppError> {
+    let Some(mode) = q.response_mode.as_deref() else {
+        return Ok(());
+    };
+    if !RESPONSE_MODES.contains(&mode) {
+        return Err(AppError::BadRequest(
+            "invalid_request",
+            "unsupported response_mode".to_string(),
+        ));
+    }
+    // A form or fragment response is rendered or followed by the browser as
+    // is, so a `javascript:` or similar URI would run on our origin
+    let web_redirect = url::Url::parse(&q.redirect_uri).is_ok_and(|url| is_web_redirect(&url));
+    if !web_redirect && matches!(split_mode(mode).0, "fragment" | "form_post") {
+        return Err(AppError::BadRequest(
+            "invalid_request",
+            "this response_mode requires an https redirect_uri".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// The mode the response is delivered in, and whether its parameters travel
+/// as one signed `response` parameter (JARM). A bare `jwt` means the response
+/// type's default mode (JARM §2.3.4).
+fn split_mode(mode: &str) -> (&str, bool) {
+    match mode {
+        "jwt" => ("query", true),
+        mode => mode
+            .strip_suffix(".jwt")
+            .map_or((mode, false), |base| (base, true)),
+    }
+}
+
+/// Deliver an authorization response to the client's redirect URI in the
+/// mode it asked for. Only for requests whose redirect URI has been checked
+/// against the client.
+pub(super) fn authorization_response(
+    conn: &rusqlite::Connection,
+    state: &AppState,
+    realm: &str,
+    realm_id: &str,
+    q: &AuthorizeQuery,
+    params: &[(&str, &str)],
+) -> Result<Response, AppError> {
+    let (mode, signed) = split_mode(q.response_mode.as_deref().unwrap_or("query"));
+    let response = if signed {
+        Some(sign_response(conn, state, realm, realm_id, q, params)?)
+    } else {
+        None
+    };
+    let params: Vec<(&str, &str)> = match &response {
+        Some(response) => vec![("response", response.as_str())],
+        None => params.to_vec(),
+    };
+
+    let mut redirect = url::Url::parse(&q.redirect_uri)
+        .map_err(|e| AppError::Internal(format!("invalid redirect_uri: {e}")))?;
+    // validate_response_mode refused forms and fragments for other redirect
+    // URIs; its error about that is all that can reach here, by query
+    let mode = if is_web_redirect(&redirect) {
+        mode
+    } else {
+        "query"
+    };
+    match mode {
+        "fragment" => {
+            let fragment = url::form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(params)
+                .finish();
+            redirect.set_fragment(Some(&fragment));
+            Ok(Redirect::to(redirect.as_str()).into_response())
+        }
+        "form_post" => {
+            let tmpl = FormPostTemplate {
+                redirect_uri: q.redirect_uri.clone(),
+                params: params
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect(),
+            };
+            let html = tmpl
+                .render()
+                .map_err(|e: askama::Error| AppError::Internal(e.to_string()))?;
+            let csp = format!("form-action {}", redirect.origin().ascii_serialization());
+            Ok((
+                [
+                    (CACHE_CONTROL, "no-store".to_string()),
+                    (CONTENT_SECURITY_POLICY, csp),
+                ],
+                Html(html),
+            )
+                .into_response())
+        }
+        _ => {
+            redirect.query_pairs_mut().extend_pairs(params);
+            Ok(Redirect::to(redirect.as_str()).into_response())
+        }
+    }
+}
+
+/// Whether the browser can be sent to a redirect URI with a form or
+/// fragment: `https`, or `http` on a loopback host.
+fn is_web_redirect(url: &url::Url) -> bool {
+    match url.scheme() {
+        "https" => true,
+        "http" => is_loopback(url),
+        _ => false,
+    }
+}
+
+/// Loopback hosts, the only ones `http` redirect URIs may use (RFC 8252 §7.3).
+pub(super) fn is_loopback(url: &url::Url) -> bool {
+    match url.host() {
+        Some(url::Host::Domain(host)) => host.eq_ignore_ascii_case("localhost"),
+        Some(url::Host::Ipv4(ip)) => ip.is_loopback(),
+        Some(url::Host::Ipv6(ip)) => ip.is_loopback(),
+        None => false,
+    }
+}
+
+/// The response parameters as a JWT signed with the realm key (JARM §2.1),
+/// expiring with the authorization code it carries.
+fn sign_response(
+    conn: &rusqlite::Connection,
+    state: &AppState,
+    realm: &str,
+    realm_id: &str,
+    q: &AuthorizeQuery,
+    params: &[(&str, &str)],
+) -> Result<String, AppError> {
+    let signing_key = db::signing_key::get_active_signing_key(conn, realm_id)?
+        .ok_or_else(|| AppError::Internal("no signing key found".to_string()))?;
+    let encoding_key = keys::encoding_key_from_pem(&signing_key.private_key_pem)
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    let lifetime = Duration::seconds(state.config.auth_code_lifetime_secs as i64);
+    let mut claims = json!({
+        "iss": format!("{}/realms/{}", state.config.issuer_base_url, realm),
+        "aud": q.client_id,
+        "exp": (Utc::now() + lifetime).timestamp(),
+    });
+    for (name, value) in params {
+        claims[*name] = Value::String(value.to_string());
+    }
+    jwt::encode_jwt(&claims, &signing_key.kid, &encoding_key)
+        .map_err(|e| AppError::Internal(e.to_string()))
+}
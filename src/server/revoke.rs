@@ -0,0 +1,59 @@
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::Form;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::client_auth::{authenticate_client, ClientAuthForm};
+use super::error::AppError;
+use super::AppState;
+use crate::crypto::token::hex_encode;
+use crate::db;
+
+#[derive(Debug, Deserialize)]
+pub struct RevokeRequest {
+    pub token: String,
+    #[serde(flatten)]
+    pub client_auth: ClientAuthForm,
+}
+
+/// POST /realms/{realm}/revoke — RFC 7009 token revocation
+pub async fn revoke(
+    State(state): State<AppState>,
+    Path(realm): Path<String>,
+    headers: HeaderMap,
+    Form(form): Form<RevokeRequest>,
+) -> Result<StatusCode, AppError> {
+    let conn = state
+        .db
+        .lock()
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let realm_obj = db::realm::get_realm_by_name(&conn, &realm)?
+        .ok_or_else(|| AppError::NotFound(format!("realm '{realm}' not found")))?;
+
+    let issuer = format!("{}/realms/{}", state.config.issuer_base_url, realm);
+    let client = authenticate_client(
+        &conn,
+        &realm_obj.id,
+        &issuer,
+        &format!("{issuer}/revoke"),
+        &headers,
+        &form.client_auth,
+    )?;
+
+    // Access tokens are self-contained JWTs and can't be revoked individually yet,
+    // so only refresh tokens are looked up, whatever `token_type_hint` says: the
+    // hint is advisory (RFC 7009 §2.1), and 200 is the answer for unknown,
+    // foreign or already-revoked tokens alike.
+    let token_hash = hex_encode(&Sha256::digest(form.token.as_bytes()));
+    if db::refresh_token::revoke_refresh_token(
+        &conn,
+        &realm_obj.id,
+        &client.client_id,
+        &token_hash,
+    )? {
+        tracing::info!("Revoked refresh token for client '{}'", client.client_id);
+    }
+
+    Ok(StatusCode::OK)
+}
@@ -0,0 +1,67 @@
+/// Whether a space-delimited scope string contains `scope`.
+pub fn has_scope(scopes: &str, scope: &str) -> bool {
+    scopes.split_whitespace().any(|s| s == scope)
+}
+
+/// Drop requested scopes the client is not allowed, keeping request order.
+pub fn downscope(requested: &str, allowed: &[String]) -> String {
+    let mut granted: Vec<&str> = Vec::new();
+    for scope in requested.split_whitespace() {
+        if allowed.iter().any(|a| a == scope) && !granted.contains(&scope) {
+            granted.push(scope);
+        }
+    }
+    granted.join(" ")
+}
+
+/// What granting `scope` lets a client do, for the consent screen.
+pub fn describe(scope: &str) -> String {
+    match scope {
+        "openid" => "Sign you in".to_string(),
+        "profile" => "See your username".to_string(),
+        "email" => "See your email address".to_string(),
+        other => format!("Use the '{other}' scope"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowed(scopes: &[&str]) -> Vec<String> {
+        scopes.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn has_scope_matches_whole_scopes_only() {
+        assert!(has_scope("openid profile", "profile"));
+        assert!(has_scope("  openid\tprofile ", "openid"));
+        assert!(!has_scope("openid", "open"));
+        assert!(!has_scope("openid-extra", "openid"));
+        assert!(!has_scope("", "openid"));
+        assert!(!has_scope("openid", ""));
+    }
+
+    #[test]
+    fn downscope_keeps_allowed_scopes_in_request_order() {
+        let allowed = allowed(&["openid", "profile", "email"]);
+        assert_eq!(downscope("email openid admin", &allowed), "email openid");
+        assert_eq!(downscope("admin", &allowed), "");
+    }
+
+    #[test]
+    fn downscope_drops_duplicates_and_blanks() {
+        let allowed = allowed(&["openid", "email"]);
+        assert_eq!(
+            downscope("openid  email openid email", &allowed),
+            "openid email"
+        );
+        assert_eq!(downscope("", &allowed), "");
+        assert_eq!(downscope("   ", &allowed), "");
+    }
+
+    #[test]
+    fn downscope_with_nothing_allowed_grants_nothing() {
+        assert_eq!(downscope("openid profile", &[]), "");
+    }
+}
@@ -0,0 +1,79 @@
+use sha2::{Digest, Sha256};
+
+use super::error::AppError;
+use crate::crypto::token::hex_encode;
+use crate::db;
+use crate::models::{Client, User};
+
+/// The `sub` a client sees for a user: the user id, or for pairwise clients
+/// a hash of the sector identifier, user id and realm salt (OIDC Core §8.1),
+/// so clients in different sectors can't correlate users.
+pub(super) fn subject_for(
+    conn: &rusqlite::Connection,
+    client: &Client,
+    user_id: &str,
+) -> Result<String, AppError> {
+    if !client.pairwise {
+        return Ok(user_id.to_string());
+    }
+
+    let salt = db::realm::get_pairwise_salt(conn, &client.realm_id)?;
+    let mut hasher = Sha256::new();
+    hasher.update(sector_identifier(client).as_bytes());
+    hasher.update(user_id.as_bytes());
+    hasher.update(salt.as_bytes());
+    let sub = hex_encode(&hasher.finalize());
+
+    db::pairwise_subject::record_pairwise_subject(conn, &client.realm_id, &sub, user_id)?;
+    Ok(sub)
+}
+
+/// The user behind a `sub` we issued, whether public or pairwise.
+pub(super) fn resolve_subject(
+    conn: &rusqlite::Connection,
+    realm_id: &str,
+    sub: &str,
+) -> Result<Option<User>, AppError> {
+    if let Some(user) = db::user::get_user_by_id(conn, sub)? {
+        return Ok(Some(user));
+    }
+    match db::pairwise_subject::get_pairwise_user_id(conn, realm_id, sub)? {
+        Some(user_id) => Ok(db::user::get_user_by_id(conn, &user_id)?),
+        None => Ok(None),
+    }
+}
+
+/// The configured sector identifier, else the host shared by the redirect
+/// URIs, else the client_id for clients without any. Dynamically registered
+/// clients pick their own redirect URIs, so nothing ties them to the host;
+/// they get their client_id, and share subjects with no other client.
+fn sector_identifier(client: &Client) -> String {
+    if let Some(sector) = &client.sector_identifier {
+        return sector.clone();
+    }
+    if client.registration_token_hash.is_some() {
+        return client.client_id.clone();
+    }
+    client
+        .redirect_uris
+        .iter()
+        .find_map(|uri| url::Url::parse(uri).ok()?.host_str().map(str::to_string))
+        .unwrap_or_else(|| client.client_id.clone())
+}
+
+/// Hosts of a set of redirect URIs, deduplicated. A pairwise client without
+/// a sector identifier must have exactly one (OIDC Core §8.1).
+pub fn redirect_hosts(redirect_uris: &[String]) -> Vec<String> {
+    let mut hosts: Vec<String> = Vec::new();
+    for uri in redirect_uris {
+        if let Some(host) = url::Url::parse(uri)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_string))
+        {
+            if !hosts.contains(&host) {
+                hosts.push(host);
+            }
+        }
+    }
+    hosts
+}
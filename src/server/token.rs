@@ -1,17 +1,29 @@
 use axum::extract::{Path, State};
+use axum::http::HeaderMap;
 use axum::Form;
 use axum::Json;
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
 use rand::RngCore;
 use serde::Deserialize;
 use serde_json::{json, Value};
 use sha2::{Digest, Sha256};
 
+use super::client_auth::{authenticate_client, ClientAuthForm};
+use super::dpop::dpop_key;
 use super::error::AppError;
+use super::introspect::decode_realm_access_token;
+use super::resource::{find_resource, resource_scope};
+use super::scope::{downscope, has_scope};
+use super::subject::{resolve_subject, subject_for};
 use super::AppState;
 use crate::crypto::{keys, pkce, token as jwt};
 use crate::db;
+use crate::models::{Client, ClientType, DeviceCodeStatus, ResourceServer, User};
+
+pub const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+pub const TOKEN_EXCHANGE_GRANT: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+const ACCESS_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:access_token";
 
 #[derive(Debug, Deserialize)]
 pub struct TokenRequest {
@@ -20,11 +32,25 @@ pub struct TokenRequest {
     pub redirect_uri: Option<String>,
     pub code_verifier: Option<String>,
     pub refresh_token: Option<String>,
+    pub scope: Option<String>,
+    pub device_code: Option<String>,
+    /// API the access token is for (RFC 8707).
+    pub resource: Option<String>,
+    /// Token exchange parameters (RFC 8693 §2.1).
+    pub subject_token: Option<String>,
+    pub subject_token_type: Option<String>,
+    pub actor_token: Option<String>,
+    pub actor_token_type: Option<String>,
+    pub requested_token_type: Option<String>,
+    pub audience: Option<String>,
+    #[serde(flatten)]
+    pub client_auth: ClientAuthForm,
 }
 
 pub async fn token(
     State(state): State<AppState>,
     Path(realm): Path<String>,
+    headers: HeaderMap,
     Form(form): Form<TokenRequest>,
 ) -> Result<Json<Value>, AppError> {
     let conn = state
@@ -34,12 +60,71 @@ pub async fn token(
     let realm_obj = db::realm::get_realm_by_name(&conn, &realm)?
         .ok_or_else(|| AppError::NotFound(format!("realm '{realm}' not found")))?;
 
+    let issuer = format!("{}/realms/{}", state.config.issuer_base_url, realm);
+    let client = authenticate_client(
+        &conn,
+        &realm_obj.id,
+        &issuer,
+        &format!("{issuer}/token"),
+        &headers,
+        &form.client_auth,
+    )?;
+    // A DPoP proof binds the issued tokens to the client's key (RFC 9449)
+    let dpop_jkt = dpop_key(
+        &conn,
+        &realm_obj.id,
+        &headers,
+        "POST",
+        &format!("{issuer}/token"),
+        None,
+    )?;
+    let dpop_jkt = dpop_jkt.as_deref();
+
+    if !grant_allowed(&client, &form.grant_type) {
+        return Err(AppError::BadRequest(
+            "unauthorized_client",
+            "client is not registered for this grant_type".to_string(),
+        ));
+    }
+
     match form.grant_type.as_str() {
-        "authorization_code" => {
-            handle_authorization_code(&conn, &state, &realm, &realm_obj.id, &form)
+        "authorization_code" => handle_authorization_code(
+            &conn,
+            &state,
+            &realm,
+            &realm_obj.id,
+            &client,
+            &form,
+            dpop_jkt,
+        ),
+        "refresh_token" => handle_refresh_token(
+            &conn,
+            &state,
+            &realm,
+            &realm_obj.id,
+            &client,
+            &form,
+            dpop_jkt,
+        ),
+        "client_credentials" => {
+            handle_client_credentials(&conn, &state, &realm, &client, &form, dpop_jkt)
         }
-        "refresh_token" => handle_refresh_token(&conn, &state, &realm, &realm_obj.id, &form),
-        _ => Err(AppError::BadRequest("unsupported grant_type".to_string())),
+        DEVICE_CODE_GRANT => handle_device_code(
+            &conn,
+            &state,
+            &realm,
+            &realm_obj.id,
+            &client,
+            &form,
+            dpop_jkt,
+        ),
+        TOKEN_EXCHANGE_GRANT => {
+            handle_token_exchange(&conn, &state, &issuer, &client, &form, dpop_jkt)
+        }
+        _ => Err(AppError::BadRequest(
+            "unsupported_grant_type",
+            "unsupported grant_type".to_string(),
+        )),
     }
 }
 
@@ -48,118 +133,437 @@ fn handle_authorization_code(
     state: &AppState,
     realm: &str,
     realm_id: &str,
+    client: &Client,
     form: &TokenRequest,
+    dpop_jkt: Option<&str>,
 ) -> Result<Json<Value>, AppError> {
     let raw_code = form
         .code
         .as_deref()
-        .ok_or_else(|| AppError::BadRequest("code is required".to_string()))?;
-    let redirect_uri = form
-        .redirect_uri
-        .as_deref()
-        .ok_or_else(|| AppError::BadRequest("redirect_uri is required".to_string()))?;
-    let code_verifier = form
-        .code_verifier
-        .as_deref()
-        .ok_or_else(|| AppError::BadRequest("code_verifier is required (PKCE)".to_string()))?;
+        .ok_or_else(|| AppError::BadRequest("invalid_request", "code is required".to_string()))?;
+    let redirect_uri = form.redirect_uri.as_deref().ok_or_else(|| {
+        AppError::BadRequest("invalid_request", "redirect_uri is required".to_string())
+    })?;
+    let code_verifier = form.code_verifier.as_deref().ok_or_else(|| {
+        AppError::BadRequest(
+            "invalid_request",
+            "code_verifier is required (PKCE)".to_string(),
+        )
+    })?;
 
     // Hash the raw code and look it up
-    let code_hash = hex_encode(&Sha256::digest(raw_code.as_bytes()));
-    let auth_code = db::auth_code::consume_auth_code(conn, &code_hash)?
-        .ok_or_else(|| AppError::BadRequest("invalid or expired authorization code".to_string()))?;
+    let code_hash = jwt::hex_encode(&Sha256::digest(raw_code.as_bytes()));
+    let auth_code = db::auth_code::get_auth_code(conn, realm_id, &code_hash)?.ok_or_else(|| {
+        AppError::BadRequest(
+            "invalid_grant",
+            "invalid or expired authorization code".to_string(),
+        )
+    })?;
+
+    // Check the code is the client's before consuming it, so nobody else can
+    // burn the client's code
+    if auth_code.client_id != client.client_id {
+        return Err(AppError::BadRequest(
+            "invalid_grant",
+            "authorization code was issued to another client".to_string(),
+        ));
+    }
+    db::auth_code::consume_auth_code(conn, &auth_code)?;
 
     // Verify redirect_uri matches
     if auth_code.redirect_uri != redirect_uri {
-        return Err(AppError::BadRequest("redirect_uri mismatch".to_string()));
+        return Err(AppError::BadRequest(
+            "invalid_grant",
+            "redirect_uri mismatch".to_string(),
+        ));
     }
 
     // Verify PKCE
     if !pkce::verify_s256(code_verifier, &auth_code.code_challenge) {
-        return Err(AppError::BadRequest("PKCE verification failed".to_string()));
+        return Err(AppError::BadRequest(
+            "invalid_grant",
+            "PKCE verification failed".to_string(),
+        ));
     }
 
+    let resource = grant_resource(auth_code.resource.as_deref(), form.resource.as_deref())?;
+    let api = find_resource(conn, realm_id, resource)?;
+
     // Look up user
     let user = db::user::get_user_by_id(conn, &auth_code.user_id)?
         .ok_or_else(|| AppError::Internal("user not found".to_string()))?;
 
+    issue_user_tokens(
+        conn,
+        state,
+        realm,
+        &UserGrant {
+            realm_id,
+            client,
+            user: &user,
+            scopes: &auth_code.scopes,
+            sid: auth_code.sid.as_deref(),
+            nonce: auth_code.nonce.as_deref(),
+            auth_time: auth_code.auth_time,
+            family_id: None,
+            code: Some(raw_code),
+            resource: api.as_ref(),
+            bound_resource: auth_code.resource.as_deref(),
+            dpop_jkt,
+        },
+    )
+}
+
+fn handle_refresh_token(
+    conn: &rusqlite::Connection,
+    state: &AppState,
+    realm: &str,
+    realm_id: &str,
+    client: &Client,
+    form: &TokenRequest,
+    dpop_jkt: Option<&str>,
+) -> Result<Json<Value>, AppError> {
+    let raw_token = form.refresh_token.as_deref().ok_or_else(|| {
+        AppError::BadRequest("invalid_request", "refresh_token is required".to_string())
+    })?;
+
+    let token_hash = jwt::hex_encode(&Sha256::digest(raw_token.as_bytes()));
+
+    let Some(old_token) = db::refresh_token::get_refresh_token(conn, realm_id, &token_hash)? else {
+        if let Some(reused) =
+            db::refresh_token::revoke_reused_token_family(conn, realm_id, &token_hash)?
+        {
+            tracing::warn!(
+                "Refresh token reuse detected for client '{}' and user '{}' in realm '{}'; \
+                 revoked token family {}",
+                reused.client_id,
+                reused.user_id,
+                realm,
+                reused.family_id
+            );
+        }
+        return Err(AppError::BadRequest(
+            "invalid_grant",
+            "invalid or expired refresh token".to_string(),
+        ));
+    };
+
+    // Check the token is the client's, and a bound token has a proof by its
+    // key, before consuming it, so nobody else can burn the client's token
+    if old_token.client_id != client.client_id {
+        return Err(AppError::BadRequest(
+            "invalid_grant",
+            "refresh token was issued to another client".to_string(),
+        ));
+    }
+    if let Some(bound) = &old_token.dpop_jkt {
+        if dpop_jkt != Some(bound.as_str()) {
+            return Err(AppError::BadRequest(
+                "invalid_grant",
+                "refresh token is bound to a DPoP key".to_string(),
+            ));
+        }
+    }
+    db::refresh_token::consume_refresh_token(conn, &old_token)?;
+
+    let resource = grant_resource(old_token.resource.as_deref(), form.resource.as_deref())?;
+    let api = find_resource(conn, realm_id, resource)?;
+
+    // Look up user
+    let user = db::user::get_user_by_id(conn, &old_token.user_id)?
+        .ok_or_else(|| AppError::Internal("user not found".to_string()))?;
+
+    // Issue a fresh set, including a new refresh token (rotation)
+    issue_user_tokens(
+        conn,
+        state,
+        realm,
+        &UserGrant {
+            realm_id,
+            client,
+            user: &user,
+            scopes: &old_token.scopes,
+            sid: old_token.sid.as_deref(),
+            // Refreshed ID tokens keep auth_time but carry no nonce (OIDC Core §12.2)
+            nonce: None,
+            auth_time: old_token.auth_time,
+            family_id: Some(&old_token.family_id),
+            code: None,
+            resource: api.as_ref(),
+            bound_resource: old_token.resource.as_deref(),
+            dpop_jkt,
+        },
+    )
+}
+
+fn handle_device_code(
+    conn: &rusqlite::Connection,
+    state: &AppState,
+    realm: &str,
+    realm_id: &str,
+    client: &Client,
+    form: &TokenRequest,
+    dpop_jkt: Option<&str>,
+) -> Result<Json<Value>, AppError> {
+    let raw_code = form.device_code.as_deref().ok_or_else(|| {
+        AppError::BadRequest("invalid_request", "device_code is required".to_string())
+    })?;
+
+    let code_hash = jwt::hex_encode(&Sha256::digest(raw_code.as_bytes()));
+    let device_code = db::device_code::get_device_code_by_hash(conn, realm_id, &code_hash)?
+        .filter(|d| d.client_id == client.client_id)
+        .ok_or_else(|| AppError::BadRequest("invalid_grant", "invalid device_code".to_string()))?;
+
+    let now = Utc::now();
+    if device_code.expires_at <= now {
+        db::device_code::delete_device_code(conn, &device_code.id)?;
+        return Err(AppError::BadRequest(
+            "expired_token",
+            "the device code has expired".to_string(),
+        ));
+    }
+
+    // RFC 8628 §3.5: polling faster than the interval earns a longer one.
+    if let Some(last) = device_code.last_polled_at {
+        if now < last + Duration::seconds(device_code.interval_secs) {
+            db::device_code::record_poll(
+                conn,
+                &device_code.id,
+                now,
+                device_code.interval_secs + 5,
+            )?;
+            return Err(AppError::BadRequest(
+                "slow_down",
+                "polling too frequently".to_string(),
+            ));
+        }
+    }
+    db::device_code::record_poll(conn, &device_code.id, now, device_code.interval_secs)?;
+
+    match (device_code.status, device_code.user_id.as_deref()) {
+        (DeviceCodeStatus::Approved, Some(user_id)) => {
+            // A mistyped resource leaves the approval for the next poll
+            let api = find_resource(conn, realm_id, form.resource.as_deref())?;
+            let user = db::user::get_user_by_id(conn, user_id)?
+                .ok_or_else(|| AppError::Internal("user not found".to_string()))?;
+            db::device_code::delete_device_code(conn, &device_code.id)?;
+            issue_user_tokens(
+                conn,
+                state,
+                realm,
+                &UserGrant {
+                    realm_id,
+                    client,
+                    user: &user,
+                    scopes: &device_code.scopes,
+                    sid: device_code.sid.as_deref(),
+                    nonce: None,
+                    auth_time: device_code.auth_time,
+                    family_id: None,
+                    code: None,
+                    resource: api.as_ref(),
+                    bound_resource: None,
+                    dpop_jkt,
+                },
+            )
+        }
+        (DeviceCodeStatus::Denied, _) => {
+            db::device_code::delete_device_code(conn, &device_code.id)?;
+            Err(AppError::BadRequest(
+                "access_denied",
+                "the user denied the request".to_string(),
+            ))
+        }
+        _ => Err(AppError::BadRequest(
+            "authorization_pending",
+            "the user has not yet approved the request".to_string(),
+        )),
+    }
+}
+
+/// A user-bound grant that has been fully validated and is ready for tokens.
+struct UserGrant<'a> {
+    realm_id: &'a str,
+    client: &'a Client,
+    user: &'a User,
+    scopes: &'a str,
+    /// Session the user authorized the grant in, if known.
+    sid: Option<&'a str>,
+    /// `nonce` from the authorization request, echoed in the ID token.
+    nonce: Option<&'a str>,
+    /// When the user authenticated, if known.
+    auth_time: Option<DateTime<Utc>>,
+    /// Refresh token family to continue, when rotating a refresh token.
+    family_id: Option<&'a str>,
+    /// Authorization code being exchanged, hashed into `c_hash`.
+    code: Option<&'a str>,
+    /// API this access token is for; None means the realm itself.
+    resource: Option<&'a ResourceServer>,
+    /// Resource the whole grant was limited to at authorization, carried
+    /// over to the refresh token.
+    bound_resource: Option<&'a str>,
+    /// Thumbprint of the DPoP key the tokens are bound to.
+    dpop_jkt: Option<&'a str>,
+}
+
+/// Whether the client may use a grant. Registered clients are held to the
+/// grant_types they registered; clients created by an administrator may use
+/// any grant their type supports.
+pub(super) fn grant_allowed(client: &Client, grant_type: &str) -> bool {
+    client
+        .grant_types
+        .as_ref()
+        .is_none_or(|grants| grants.iter().any(|g| g == grant_type))
+}
+
+/// The resource a token request gets. A grant authorized for one API stays
+/// limited to it (RFC 8707 §2.2); an unrestricted grant may name any API.
+fn grant_resource<'a>(
+    authorized: Option<&'a str>,
+    requested: Option<&'a str>,
+) -> Result<Option<&'a str>, AppError> {
+    match (authorized, requested) {
+        (Some(authorized), Some(requested)) if authorized != requested => Err(
+            AppError::BadRequest("invalid_target", "unknown resource".to_string()),
+        ),
+        (authorized, requested) => Ok(authorized.or(requested)),
+    }
+}
+
+/// Mint the access, ID and refresh tokens for a user-bound grant.
+fn issue_user_tokens(
+    conn: &rusqlite::Connection,
+    state: &AppState,
+    realm: &str,
+    grant: &UserGrant,
+) -> Result<Json<Value>, AppError> {
     // Get signing key
-    let signing_key = db::signing_key::get_active_signing_key(conn, realm_id)?
+    let signing_key = db::signing_key::get_active_signing_key(conn, grant.realm_id)?
         .ok_or_else(|| AppError::Internal("no signing key found".to_string()))?;
 
     let issuer = format!("{}/realms/{}", state.config.issuer_base_url, realm);
     let encoding_key = keys::encoding_key_from_pem(&signing_key.private_key_pem)
         .map_err(|e| AppError::Internal(e.to_string()))?;
 
-    // Build ID token
-    let id_claims = jwt::build_id_token_claims(
+    let sub = subject_for(conn, grant.client, &grant.user.id)?;
+
+    // Build access token, for the requested API or else the realm itself
+    let (audience, access_scope) = match grant.resource {
+        Some(api) => (api.identifier.as_str(), resource_scope(grant.scopes, api)),
+        None => (issuer.as_str(), grant.scopes.to_string()),
+    };
+    let mut access_claims = jwt::build_access_token_claims(
         &issuer,
-        &user.id,
-        &auth_code.client_id,
-        state.config.id_token_lifetime_secs,
-        &user.username,
-        &user.email,
-        None, // nonce is not stored in auth_code in this implementation
+        &sub,
+        audience,
+        state.config.access_token_lifetime_secs,
+        &access_scope,
+        &grant.client.client_id,
     );
-    let id_token = jwt::encode_jwt(&id_claims, &signing_key.kid, &encoding_key)
+    access_claims.auth_time = grant.auth_time.map(|t| t.timestamp());
+    access_claims.acr = Some(jwt::PASSWORD_ACR.to_string());
+    add_identity_claims(&mut access_claims, grant.user);
+    access_claims.cnf = confirmation(grant.dpop_jkt);
+    let access_token = jwt::encode_access_token(&access_claims, &signing_key.kid, &encoding_key)
         .map_err(|e| AppError::Internal(e.to_string()))?;
 
-    // Build access token
-    let access_claims = jwt::build_access_token_claims(
-        &issuer,
-        &user.id,
+    // Build ID token, bound to the access token and code issued with it
+    let hash_alg = state.config.id_token_hash_alg;
+    let mut id_claims = jwt::build_id_token_claims(
         &issuer,
-        state.config.access_token_lifetime_secs,
-        &auth_code.scopes,
-        &auth_code.client_id,
+        &sub,
+        &grant.client.client_id,
+        state.config.id_token_lifetime_secs,
+        has_scope(grant.scopes, "profile").then_some(grant.user.username.as_str()),
+        has_scope(grant.scopes, "email").then_some(grant.user.email.as_str()),
+        grant.nonce.map(str::to_string),
     );
-    let access_token = jwt::encode_jwt(&access_claims, &signing_key.kid, &encoding_key)
+    id_claims.auth_time = grant.auth_time.map(|t| t.timestamp());
+    id_claims.sid = grant.sid.map(str::to_string);
+    id_claims.at_hash = Some(jwt::token_hash(&access_token, hash_alg));
+    id_claims.c_hash = grant.code.map(|code| jwt::token_hash(code, hash_alg));
+    let id_token = jwt::encode_jwt(&id_claims, &signing_key.kid, &encoding_key)
         .map_err(|e| AppError::Internal(e.to_string()))?;
 
-    // Issue refresh token
-    let raw_refresh = generate_random_token();
-    let refresh_hash = hex_encode(&Sha256::digest(raw_refresh.as_bytes()));
-    let refresh_lifetime = Duration::seconds(state.config.refresh_token_lifetime_secs as i64);
-    let refresh_expires = Utc::now() + refresh_lifetime;
-    db::refresh_token::insert_refresh_token(
-        conn,
-        realm_id,
-        &auth_code.client_id,
-        &user.id,
-        &refresh_hash,
-        &auth_code.scopes,
-        refresh_expires,
-    )?;
-
-    Ok(Json(json!({
+    let mut response = json!({
         "access_token": access_token,
-        "token_type": "Bearer",
+        "token_type": token_type(grant.dpop_jkt),
         "expires_in": state.config.access_token_lifetime_secs,
-        "id_token": id_token,
-        "refresh_token": raw_refresh,
-    })))
+        "scope": access_scope,
+    });
+
+    // Issue refresh token, unless the client can't redeem one
+    if grant_allowed(grant.client, "refresh_token") {
+        let raw_refresh = generate_random_token();
+        let refresh_hash = jwt::hex_encode(&Sha256::digest(raw_refresh.as_bytes()));
+        let refresh_lifetime = Duration::seconds(state.config.refresh_token_lifetime_secs as i64);
+        let refresh_expires = Utc::now() + refresh_lifetime;
+        db::refresh_token::insert_refresh_token(
+            conn,
+            &db::refresh_token::NewRefreshToken {
+                realm_id: grant.realm_id,
+                client_id: &grant.client.client_id,
+                user_id: &grant.user.id,
+                token_hash: &refresh_hash,
+                scopes: grant.scopes,
+                sid: grant.sid,
+                auth_time: grant.auth_time,
+                family_id: grant.family_id,
+                resource: grant.bound_resource,
+                // Only public clients' refresh tokens are bound (RFC 9449 §5);
+                // confidential clients already authenticate to use theirs
+                dpop_jkt: grant
+                    .dpop_jkt
+                    .filter(|_| grant.client.client_type == ClientType::Public),
+                expires_at: refresh_expires,
+            },
+        )?;
+        response["refresh_token"] = json!(raw_refresh);
+    }
+    // Device grants need not be OpenID requests; only those get an ID token.
+    if has_scope(grant.scopes, "openid") {
+        response["id_token"] = json!(id_token);
+    }
+    Ok(Json(response))
 }
 
-fn handle_refresh_token(
+fn handle_client_credentials(
     conn: &rusqlite::Connection,
     state: &AppState,
     realm: &str,
-    realm_id: &str,
+    client: &Client,
     form: &TokenRequest,
+    dpop_jkt: Option<&str>,
 ) -> Result<Json<Value>, AppError> {
-    let raw_token = form
-        .refresh_token
-        .as_deref()
-        .ok_or_else(|| AppError::BadRequest("refresh_token is required".to_string()))?;
-
-    let token_hash = hex_encode(&Sha256::digest(raw_token.as_bytes()));
-    let old_token = db::refresh_token::consume_refresh_token(conn, &token_hash)?
-        .ok_or_else(|| AppError::BadRequest("invalid or expired refresh token".to_string()))?;
+    if client.client_type != ClientType::Confidential {
+        return Err(AppError::BadRequest(
+            "unauthorized_client",
+            "client_credentials requires a confidential client".to_string(),
+        ));
+    }
 
-    // Look up user
-    let user = db::user::get_user_by_id(conn, &old_token.user_id)?
-        .ok_or_else(|| AppError::Internal("user not found".to_string()))?;
+    // Scopes default to everything the client is allowed, and a request may
+    // only narrow that set.
+    let scopes: Vec<&str> = match form.scope.as_deref() {
+        Some(requested) => requested.split_whitespace().collect(),
+        None => client.allowed_scopes.iter().map(String::as_str).collect(),
+    };
+    if let Some(scope) = scopes
+        .iter()
+        .find(|s| !client.allowed_scopes.iter().any(|a| a == *s))
+    {
+        return Err(AppError::BadRequest(
+            "invalid_scope",
+            format!("scope '{scope}' is not allowed for this client"),
+        ));
+    }
+    let realm_id = &client.realm_id;
+    let api = find_resource(conn, realm_id, form.resource.as_deref())?;
+    let scope = match &api {
+        Some(api) => resource_scope(&scopes.join(" "), api),
+        None => scopes.join(" "),
+    };
 
-    // Get signing key
     let signing_key = db::signing_key::get_active_signing_key(conn, realm_id)?
         .ok_or_else(|| AppError::Internal("no signing key found".to_string()))?;
 
@@ -167,61 +571,292 @@ fn handle_refresh_token(
     let encoding_key = keys::encoding_key_from_pem(&signing_key.private_key_pem)
         .map_err(|e| AppError::Internal(e.to_string()))?;
 
-    // New access token
-    let access_claims = jwt::build_access_token_claims(
-        &issuer,
-        &user.id,
+    // The client acts on its own behalf, so it is also the subject.
+    let audience = api.as_ref().map_or(issuer.as_str(), |api| &api.identifier);
+    let mut access_claims = jwt::build_access_token_claims(
         &issuer,
+        &client.client_id,
+        audience,
         state.config.access_token_lifetime_secs,
-        &old_token.scopes,
-        &old_token.client_id,
+        &scope,
+        &client.client_id,
     );
-    let access_token = jwt::encode_jwt(&access_claims, &signing_key.kid, &encoding_key)
+    access_claims.cnf = confirmation(dpop_jkt);
+    let access_token = jwt::encode_access_token(&access_claims, &signing_key.kid, &encoding_key)
         .map_err(|e| AppError::Internal(e.to_string()))?;
 
-    // New ID token
-    let id_claims = jwt::build_id_token_claims(
-        &issuer,
-        &user.id,
-        &old_token.client_id,
-        state.config.id_token_lifetime_secs,
-        &user.username,
-        &user.email,
-        None,
-    );
-    let id_token = jwt::encode_jwt(&id_claims, &signing_key.kid, &encoding_key)
-        .map_err(|e| AppError::Internal(e.to_string()))?;
+    Ok(Json(json!({
+        "access_token": access_token,
+        "token_type": token_type(dpop_jkt),
+        "expires_in": state.config.access_token_lifetime_secs,
+        "scope": scope,
+    })))
+}
+
+/// RFC 8693 token exchange: swap a user's access token for one to another
+/// API, with at most the scopes the original carried. With an actor token
+/// the new token records the actor in `act` (delegation); without one the
+/// client simply impersonates the user. Which clients may exchange toward
+/// which API is set with `anz api allow-exchange`.
+fn handle_token_exchange(
+    conn: &rusqlite::Connection,
+    state: &AppState,
+    issuer: &str,
+    client: &Client,
+    form: &TokenRequest,
+    dpop_jkt: Option<&str>,
+) -> Result<Json<Value>, AppError> {
+    if client.client_type != ClientType::Confidential {
+        return Err(AppError::BadRequest(
+            "unauthorized_client",
+            "token exchange requires a confidential client".to_string(),
+        ));
+    }
+    let realm_id = &client.realm_id;
+
+    let subject_token = form.subject_token.as_deref().ok_or_else(|| {
+        AppError::BadRequest("invalid_request", "subject_token is required".to_string())
+    })?;
+    if form.subject_token_type.as_deref() != Some(ACCESS_TOKEN_TYPE) {
+        return Err(AppError::BadRequest(
+            "invalid_request",
+            "subject_token_type must be an access token".to_string(),
+        ));
+    }
+    if form
+        .requested_token_type
+        .as_deref()
+        .is_some_and(|t| t != ACCESS_TOKEN_TYPE)
+    {
+        return Err(AppError::BadRequest(
+            "invalid_request",
+            "only access tokens can be requested".to_string(),
+        ));
+    }
 
-    // New refresh token (rotation)
-    let new_raw_refresh = generate_random_token();
-    let new_refresh_hash = hex_encode(&Sha256::digest(new_raw_refresh.as_bytes()));
-    let refresh_lifetime = Duration::seconds(state.config.refresh_token_lifetime_secs as i64);
-    let refresh_expires = Utc::now() + refresh_lifetime;
-    db::refresh_token::insert_refresh_token(
+    // The target API, named as a resource or by its identifier as audience
+    let target = match (form.resource.as_deref(), form.audience.as_deref()) {
+        (Some(resource), Some(audience)) if resource != audience => {
+            return Err(AppError::BadRequest(
+                "invalid_target",
+                "unknown resource".to_string(),
+            ))
+        }
+        (Some(target), _) | (None, Some(target)) => target,
+        (None, None) => {
+            return Err(AppError::BadRequest(
+                "invalid_request",
+                "resource or audience is required".to_string(),
+            ))
+        }
+    };
+    let api = find_resource(conn, realm_id, Some(target))?
+        .ok_or_else(|| AppError::BadRequest("invalid_target", "unknown resource".to_string()))?;
+    if !db::exchange_policy::is_token_exchange_allowed(
         conn,
         realm_id,
-        &old_token.client_id,
-        &user.id,
-        &new_refresh_hash,
-        &old_token.scopes,
-        refresh_expires,
-    )?;
+        &client.client_id,
+        &api.identifier,
+    )? {
+        tracing::warn!(
+            "Client '{}' may not exchange tokens for '{}'",
+            client.client_id,
+            api.identifier
+        );
+        return Err(AppError::BadRequest(
+            "unauthorized_client",
+            "client may not exchange tokens for this audience".to_string(),
+        ));
+    }
+
+    let subject =
+        decode_realm_access_token(conn, realm_id, issuer, subject_token)?.ok_or_else(|| {
+            AppError::BadRequest("invalid_request", "invalid subject_token".to_string())
+        })?;
+    // Only tokens the client could have been handed are exchanged: ones for
+    // the realm itself, or for an API the client exchanges tokens for
+    if subject.aud != issuer
+        && !db::exchange_policy::is_token_exchange_allowed(
+            conn,
+            realm_id,
+            &client.client_id,
+            &subject.aud,
+        )?
+    {
+        return Err(AppError::BadRequest(
+            "invalid_request",
+            "subject_token is not for an audience this client accepts".to_string(),
+        ));
+    }
+    // A DPoP-bound subject token is only exchanged with a proof by its key,
+    // and the new token stays bound to that key
+    if let Some(cnf) = &subject.cnf {
+        if dpop_jkt != Some(cnf.jkt.as_str()) {
+            return Err(AppError::BadRequest(
+                "invalid_request",
+                "subject_token is bound to a DPoP key".to_string(),
+            ));
+        }
+    }
+    let user = resolve_subject(conn, realm_id, &subject.sub)?.ok_or_else(|| {
+        AppError::BadRequest("invalid_request", "subject_token has no user".to_string())
+    })?;
+
+    let act = match form.actor_token.as_deref() {
+        Some(actor_token) => {
+            if form.actor_token_type.as_deref() != Some(ACCESS_TOKEN_TYPE) {
+                return Err(AppError::BadRequest(
+                    "invalid_request",
+                    "actor_token_type must be an access token".to_string(),
+                ));
+            }
+            let actor = decode_realm_access_token(conn, realm_id, issuer, actor_token)?
+                .ok_or_else(|| {
+                    AppError::BadRequest("invalid_request", "invalid actor_token".to_string())
+                })?;
+            // The client can only name itself as the actor
+            if actor.client_id != client.client_id {
+                return Err(AppError::BadRequest(
+                    "invalid_request",
+                    "actor_token was not issued to this client".to_string(),
+                ));
+            }
+            // Earlier actors in the chain nest inside the new one (§4.1)
+            Some(jwt::Actor {
+                sub: actor.sub,
+                act: subject.act.map(Box::new),
+            })
+        }
+        None => subject.act,
+    };
+
+    // Scopes can only narrow: to what was asked for, then to the API's
+    let scope = match form.scope.as_deref() {
+        Some(requested) => {
+            let held: Vec<String> = subject
+                .scope
+                .split_whitespace()
+                .map(str::to_string)
+                .collect();
+            downscope(requested, &held)
+        }
+        None => subject.scope.clone(),
+    };
+    let scope = resource_scope(&scope, &api);
+    if scope.is_empty() {
+        return Err(AppError::BadRequest(
+            "invalid_scope",
+            "no requested scope can be granted".to_string(),
+        ));
+    }
+
+    let signing_key = db::signing_key::get_active_signing_key(conn, realm_id)?
+        .ok_or_else(|| AppError::Internal("no signing key found".to_string()))?;
+    let encoding_key = keys::encoding_key_from_pem(&signing_key.private_key_pem)
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    // The new token must not outlive the one it was exchanged for
+    let remaining = (subject.exp - Utc::now().timestamp()).max(0) as u64;
+    let lifetime = state.config.access_token_lifetime_secs.min(remaining);
+    let mut access_claims = jwt::build_access_token_claims(
+        issuer,
+        &subject_for(conn, client, &user.id)?,
+        &api.identifier,
+        lifetime,
+        &scope,
+        &client.client_id,
+    );
+    access_claims.auth_time = subject.auth_time;
+    access_claims.acr = subject.acr.clone();
+    add_identity_claims(&mut access_claims, &user);
+    access_claims.act = act;
+    access_claims.cnf = confirmation(dpop_jkt);
+    let access_token = jwt::encode_access_token(&access_claims, &signing_key.kid, &encoding_key)
+        .map_err(|e| AppError::Internal(e.to_string()))?;
 
     Ok(Json(json!({
         "access_token": access_token,
-        "token_type": "Bearer",
-        "expires_in": state.config.access_token_lifetime_secs,
-        "id_token": id_token,
-        "refresh_token": new_raw_refresh,
+        "issued_token_type": ACCESS_TOKEN_TYPE,
+        "token_type": token_type(dpop_jkt),
+        "expires_in": lifetime,
+        "scope": scope,
     })))
 }
 
+/// Identity claims for the scopes an access token carries (RFC 9068 §2.2.2).
+fn add_identity_claims(claims: &mut jwt::AccessTokenClaims, user: &User) {
+    if has_scope(&claims.scope, "profile") {
+        claims.preferred_username = Some(user.username.clone());
+    }
+    if has_scope(&claims.scope, "email") {
+        claims.email = Some(user.email.clone());
+    }
+}
+
+fn confirmation(dpop_jkt: Option<&str>) -> Option<jwt::Confirmation> {
+    dpop_jkt.map(|jkt| jwt::Confirmation {
+        jkt: jkt.to_string(),
+    })
+}
+
+/// DPoP-bound tokens are presented with the `DPoP` scheme instead.
+fn token_type(dpop_jkt: Option<&str>) -> &'static str {
+    if dpop_jkt.is_some() {
+        "DPoP"
+    } else {
+        "Bearer"
+    }
+}
+
 fn generate_random_token() -> String {
     let mut bytes = [0u8; 32];
     rand::thread_rng().fill_bytes(&mut bytes);
     URL_SAFE_NO_PAD.encode(bytes)
 }
 
-fn hex_encode(bytes: &[u8]) -> String {
-    bytes.iter().map(|b| format!("{b:02x}")).collect()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const API: &str = "https://api.example";
+
+    fn is_invalid_target(result: Result<impl std::fmt::Debug, AppError>) -> bool {
+        matches!(result, Err(AppError::BadRequest("invalid_target", _)))
+    }
+
+    #[test]
+    fn grant_resource_keeps_a_grant_to_its_api() {
+        assert_eq!(grant_resource(Some(API), None).unwrap(), Some(API));
+        assert_eq!(grant_resource(Some(API), Some(API)).unwrap(), Some(API));
+        assert!(is_invalid_target(grant_resource(
+            Some(API),
+            Some("https://other.example")
+        )));
+    }
+
+    #[test]
+    fn grant_resource_lets_an_unrestricted_grant_name_any_api() {
+        assert_eq!(grant_resource(None, None).unwrap(), None);
+        assert_eq!(grant_resource(None, Some(API)).unwrap(), Some(API));
+    }
+
+    #[test]
+    fn unregistered_resource_is_invalid_target() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        db::migrations::run_migrations(&conn).unwrap();
+        let realm = db::realm::create_realm(&conn, "r1").unwrap();
+        db::resource_server::create_resource_server(&conn, &realm.id, API, None, &[]).unwrap();
+
+        let resource = grant_resource(None, Some(API)).unwrap();
+        let api = find_resource(&conn, &realm.id, resource).unwrap().unwrap();
+        assert_eq!(api.identifier, API);
+
+        let resource = grant_resource(None, Some("https://unknown.example")).unwrap();
+        assert!(is_invalid_target(find_resource(&conn, &realm.id, resource)));
+        assert!(is_invalid_target(find_resource(
+            &conn,
+            "other-realm",
+            Some(API)
+        )));
+    }
 }
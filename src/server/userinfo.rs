@@ -3,7 +3,10 @@ use axum::http::HeaderMap;
 use axum::Json;
 use serde_json::{json, Value};
 
+use super::dpop::{check_token_binding, extract_access_token};
 use super::error::AppError;
+use super::scope::has_scope;
+use super::subject::resolve_subject;
 use super::AppState;
 use crate::crypto::{keys, token as jwt};
 use crate::db;
@@ -13,7 +16,7 @@ pub async fn userinfo(
     Path(realm): Path<String>,
     headers: HeaderMap,
 ) -> Result<Json<Value>, AppError> {
-    let bearer = extract_bearer(&headers)?;
+    let presented = extract_access_token(&headers)?;
 
     let conn = state
         .db
@@ -29,28 +32,55 @@ pub async fn userinfo(
     let decoding_key = keys::decoding_key_from_pem(&signing_key.public_key_pem)
         .map_err(|e| AppError::Internal(e.to_string()))?;
 
-    let claims = jwt::decode_access_token(&bearer, &decoding_key, &issuer)
-        .map_err(|_| AppError::Unauthorized("invalid access token".to_string()))?;
+    let claims = jwt::decode_access_token(&presented.token, &decoding_key, &issuer, &[&issuer])
+        .map_err(|_| {
+            AppError::Unauthorized(
+                "invalid_token",
+                "invalid or expired access token".to_string(),
+            )
+        })?;
+    check_token_binding(
+        &conn,
+        &realm_obj.id,
+        &headers,
+        "GET",
+        &format!("{issuer}/userinfo"),
+        &presented,
+        claims.cnf.as_ref(),
+    )?;
 
-    let user = db::user::get_user_by_id(&conn, &claims.sub)?
-        .ok_or_else(|| AppError::Internal("user not found".to_string()))?;
+    // Tokens from the client_credentials grant have no user behind them.
+    let user = resolve_subject(&conn, &realm_obj.id, &claims.sub)?.ok_or_else(|| {
+        AppError::Unauthorized("invalid_token", "access token has no user".to_string())
+    })?;
 
-    Ok(Json(json!({
-        "sub": user.id,
-        "preferred_username": user.username,
-        "email": user.email,
-    })))
+    if !has_scope(&claims.scope, "openid") {
+        return Err(AppError::Unauthorized(
+            "insufficient_scope",
+            "access token lacks the openid scope".to_string(),
+        ));
+    }
+
+    // Echo the subject the client was given, which may be pairwise
+    let mut info = json!({ "sub": claims.sub });
+    if has_scope(&claims.scope, "profile") {
+        info["preferred_username"] = json!(user.username);
+    }
+    if has_scope(&claims.scope, "email") {
+        info["email"] = json!(user.email);
+    }
+    Ok(Json(info))
 }
 
-fn extract_bearer(headers: &HeaderMap) -> Result<String, AppError> {
+pub(super) fn extract_bearer(headers: &HeaderMap) -> Result<String, AppError> {
     let auth = headers
         .get(axum::http::header::AUTHORIZATION)
         .and_then(|v| v.to_str().ok())
-        .ok_or_else(|| AppError::Unauthorized("missing Authorization header".to_string()))?;
+        .ok_or(AppError::MissingToken)?;
 
-    let token = auth
-        .strip_prefix("Bearer ")
-        .ok_or_else(|| AppError::Unauthorized("expected Bearer token".to_string()))?;
+    let token = auth.strip_prefix("Bearer ").ok_or_else(|| {
+        AppError::Unauthorized("invalid_request", "expected Bearer token".to_string())
+    })?;
 
     Ok(token.to_string())
 }